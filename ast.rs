@@ -0,0 +1,128 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Abstract syntax
+//
+//  The parser builds these as it goes.  Every statement and expression
+//  has the span of source it was parsed from (see span.rs), for
+//  diagnostics; binary operators also keep the span of the operator
+//  itself, which is where an error like division by zero is reported.
+//
+//  With the optional serde feature, the tree can be serialized (see
+//  --ast-json in main.rs).
+//
+
+pub use crate::span::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum Type { Int, Real }
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self { Type::Int => "int", Type::Real => "real" })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum BinOp { Add, Sub, Mul, Div, Mod, Pow, BitAnd, BitOr, BitXor }
+
+impl BinOp {
+    // & | and ^, which work on the bits of an int, so don't take reals.
+    pub fn is_bitwise(self) -> bool {
+        matches!(self, BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum CompOp { Eq, Ne, Lt, Gt, Le, Ge }
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum Expr {
+    Ident { name: String, span: Span },
+    ILit(i64, Span),
+    RLit(f64, Span),
+    Neg { operand: Box<Expr>, span: Span },
+    Trunc(Box<Expr>, Span),     // real to int
+    Float(Box<Expr>, Span),     // int to real
+    Binary { op: BinOp, lhs: Box<Expr>, rhs: Box<Expr>, span: Span, op_span: Span },
+    // the value of a chained assignment, as in a := b := 1, which stores
+    // value in name and then yields it; only ever the value of an Assign
+    Assign { name: String, value: Box<Expr>, span: Span },
+    // (cond ? then : otherwise), spanning the parentheses
+    Ternary { cond: Box<Cond>, then: Box<Expr>, otherwise: Box<Expr>, span: Span },
+}
+
+impl Expr {
+    // lhs op rhs, spanning both operands; op_span is the operator's.
+    pub fn binary(op: BinOp, lhs: Expr, rhs: Expr, op_span: Span) -> Expr {
+        let span = Span::merge(lhs.span(), rhs.span());
+        Expr::Binary { op, lhs: Box::new(lhs), rhs: Box::new(rhs), span, op_span }
+    }
+
+    pub fn span(&self) -> Span {
+        match *self {
+            Expr::Ident { span, .. } | Expr::ILit(_, span) | Expr::RLit(_, span)
+            | Expr::Neg { span, .. } | Expr::Trunc(_, span) | Expr::Float(_, span)
+            | Expr::Binary { span, .. } | Expr::Assign { span, .. }
+            | Expr::Ternary { span, .. } => span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct Cond {
+    pub lhs: Expr,
+    pub op: CompOp,
+    pub rhs: Expr,
+}
+
+impl Cond {
+    pub fn span(&self) -> Span {
+        Span::merge(self.lhs.span(), self.rhs.span())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum WriteArg {
+    Expr(Expr),
+    Str(String, Span),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub enum Stmt {
+    Assign { name: String, expr: Expr, span: Span },
+    // expr is None for a bare declaration, which starts the variable at 0
+    Decl { tp: Type, name: String, expr: Option<Expr>, span: Span },
+    Const { tp: Type, name: String, expr: Expr, span: Span },
+    Read { tp: Option<Type>, names: Vec<String>, span: Span },
+    Write { args: Vec<WriteArg>, span: Span },
+    // if and any elsifs, in order, each a condition and its body
+    If { branches: Vec<(Cond, Vec<Stmt>)>, else_body: Option<Vec<Stmt>>, span: Span },
+    Do { body: Vec<Stmt>, span: Span },
+    For { var: String, from: Expr, to: Expr, body: Vec<Stmt>, span: Span },
+    Check { cond: Cond, span: Span },
+    Assert { cond: Cond, span: Span },      // stop the program if cond fails
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match *self {
+            Stmt::Assign { span, .. } | Stmt::Decl { span, .. } | Stmt::Const { span, .. }
+            | Stmt::Read { span, .. } | Stmt::Write { span, .. } | Stmt::If { span, .. }
+            | Stmt::Do { span, .. } | Stmt::For { span, .. } | Stmt::Check { span, .. }
+            | Stmt::Assert { span, .. } => span,
+        }
+    }
+
+    // The line the statement starts on, which semantic and runtime
+    // errors report.
+    pub fn line(&self) -> usize {
+        self.span().start_line
+    }
+}
@@ -0,0 +1,135 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Diagnostics
+//
+//  Every error the scanner, parser, or semantic checks can report, with
+//  its position, so callers can format or aggregate them as they like.
+//  (Errors while running a program are eval::RuntimeError.)
+//
+
+use crate::scanner::TokTp;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    // bad token; position is where the token began
+    Lex { msg: String, line: usize, col: usize },
+    // unexpected token; position is that of the token found, and index
+    // is its Token::index (how many tokens came before it)
+    Syntax { expected: Vec<TokTp>, found: TokTp, line: usize, col: usize, index: usize },
+    // missing close for an open token (a brace) on open_line; position is
+    // that of the token found instead
+    Unmatched { open: TokTp, open_line: usize, found: TokTp, line: usize, col: usize },
+    // undeclared variable, type mismatch, ...; line of the statement
+    Semantic { msg: String, line: usize },
+}
+
+impl CalcError {
+    pub fn line(&self) -> usize {
+        match *self {
+            CalcError::Lex { line, .. }
+            | CalcError::Syntax { line, .. }
+            | CalcError::Unmatched { line, .. }
+            | CalcError::Semantic { line, .. } => line,
+        }
+    }
+
+    // Semantic errors are tied to a whole statement, so have no column.
+    pub fn col(&self) -> Option<usize> {
+        match *self {
+            CalcError::Lex { col, .. } | CalcError::Syntax { col, .. }
+            | CalcError::Unmatched { col, .. } => Some(col),
+            CalcError::Semantic { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CalcError::Lex { ref msg, line, col } => {
+                write!(f, "{} on line {}, col {}", msg, line, col)
+            }
+            CalcError::Syntax { ref expected, ref found, line, col, index } => {
+                write!(f, "syntax error on line {}, col {} (token {}): expected ",
+                    line, col, index)?;
+                if expected.len() > 1 {
+                    write!(f, "one of ")?;
+                }
+                for (i, tp) in expected.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", tp)?;
+                }
+                write!(f, "; found {:?}", found)
+            }
+            CalcError::Unmatched { ref open, open_line, ref found, line, col } => {
+                write!(f, "syntax error on line {}, col {}: unmatched {:?} on line {}; found {:?}",
+                    line, col, open, open_line, found)
+            }
+            CalcError::Semantic { ref msg, line } => write!(f, "{} on line {}", msg, line),
+        }
+    }
+}
+
+impl Error for CalcError {}
+
+// msg, followed by line_text with a caret under display column col, e.g.
+//
+//     syntax error on line 2, col 5 (token 3): expected ...
+//       x := := 1
+//            ^
+//
+// Tabs in line_text are expanded to stops every tab_width columns, as
+// the scanner counted them, so the caret lines up however the terminal
+// shows them.
+pub fn format_diagnostic(line_text: &str, col: usize, tab_width: usize, msg: &str) -> String {
+    let mut text = String::new();
+    let mut width = 0;
+    for ch in line_text.chars() {
+        if ch == '\t' {
+            let stop = (width / tab_width + 1) * tab_width;
+            text.extend((width..stop).map(|_| ' '));
+            width = stop;
+        } else {
+            text.push(ch);
+            width += 1;
+        }
+    }
+    format!("{}\n  {}\n  {}^", msg, text.trim_end(), " ".repeat(col))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let lex = CalcError::Lex { msg: "unexpected character '@' (0x40)".to_string(),
+            line: 2, col: 7 };
+        assert_eq!(lex.to_string(), "unexpected character '@' (0x40) on line 2, col 7");
+        let syntax = CalcError::Syntax { expected: vec![TokTp::Ident, TokTp::ILit],
+            found: TokTp::Times, line: 1, col: 5, index: 2 };
+        assert_eq!(syntax.to_string(),
+            "syntax error on line 1, col 5 (token 2): expected one of Ident, ILit; found Times");
+        let unmatched = CalcError::Unmatched { open: TokTp::LBrace, open_line: 1,
+            found: TokTp::Fi, line: 3, col: 0 };
+        assert_eq!(unmatched.to_string(),
+            "syntax error on line 3, col 0: unmatched LBrace on line 1; found Fi");
+        let semantic = CalcError::Semantic { msg: "use of undeclared variable 'x'".to_string(),
+            line: 3 };
+        assert_eq!(semantic.to_string(), "use of undeclared variable 'x' on line 3");
+        assert_eq!((semantic.line(), semantic.col()), (3, None));
+    }
+
+    #[test]
+    fn caret_under_column() {
+        assert_eq!(format_diagnostic("x := := 1", 5, 8, "oops"), "oops\n  x := := 1\n       ^");
+        assert_eq!(format_diagnostic("\tx @", 10, 8, "oops"),
+            "oops\n          x @\n            ^");
+        assert_eq!(format_diagnostic("ab\t\t@", 16, 8, "m"),
+            "m\n  ab              @\n                  ^");
+        assert_eq!(format_diagnostic("ab\t\t@", 8, 4, "m"), "m\n  ab      @\n          ^");
+    }
+}
@@ -0,0 +1,757 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Interpreter
+//
+//  Walks the AST.  Ints and reals combine to a real; otherwise operators
+//  keep the type of their operands.  Likewise an int stored in a real
+//  variable becomes a real (see the promotion table in semantic.rs).  A
+//  check whose condition is false leaves the innermost enclosing do or
+//  for loop; a false assert stops the program with a runtime error.  Int
+//  division or remainder by zero is a runtime error, as is int overflow;
+//  real arithmetic follows IEEE 754, so a real divided by zero is
+//  infinite (or NaN for 0.0 / 0).
+//
+//  When the program's input is a terminal, a read first prints a prompt,
+//  so the user knows the program is waiting; input from a pipe or file
+//  gets none, to keep the output clean.
+//
+
+use crate::ast::*;
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Index;
+use std::io;
+use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Real(f64),
+}
+
+impl Value {
+    fn as_real(self) -> f64 {
+        match self {
+            Value::Int(i) => i as f64,
+            Value::Real(r) => r,
+        }
+    }
+
+    // The value to store in a variable of type tp: an int is promoted if
+    // tp is real.
+    fn promote(self, tp: Type) -> Value {
+        match (self, tp) {
+            (Value::Int(i), Type::Real) => Value::Real(i as f64),
+            (v, _) => v,
+        }
+    }
+}
+
+// How write shows a value: a real always has a decimal point, even when
+// it's whole (3.0, not 3), so it can't be mistaken for an int.  Infinities
+// and NaN print as inf, -inf and NaN.
+pub fn format_value(v: &Value) -> String {
+    match *v {
+        Value::Int(i) => i.to_string(),
+        Value::Real(r) => {
+            let text = r.to_string();
+            if text.contains('.') || !r.is_finite() { text } else { text + ".0" }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_value(self))
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: usize,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "runtime error on line {}: {}", self.line, self.message)
+    }
+}
+
+// The variables, and which of them are constants.  There's one flat
+// namespace: the nested scopes the semantic checker sees don't exist at
+// run time, so an inner declaration simply replaces an outer variable
+// (and a constant replaced by a variable is no longer constant).
+#[derive(Debug, Default)]
+pub struct Env {
+    vars: HashMap<String, Value>,
+    consts: HashSet<String>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.vars.get(name).cloned()
+    }
+
+    // Give a variable a value, declaring it if it's new.
+    pub fn insert(&mut self, name: String, v: Value) {
+        self.consts.remove(&name);
+        self.vars.insert(name, v);
+    }
+
+    pub fn is_const(&self, name: &str) -> bool {
+        self.consts.contains(name)
+    }
+
+    fn set_const(&mut self, name: String, v: Value) {
+        self.consts.insert(name.clone());
+        self.vars.insert(name, v);
+    }
+
+    // Store into an existing variable, unless it's a constant, promoting
+    // an int if the variable is real.  Returns the value stored.
+    fn assign(&mut self, name: &str, v: Value, line: usize) -> Result<Value, RuntimeError> {
+        if self.is_const(name) {
+            return Err(RuntimeError {
+                message: format!("cannot assign to constant '{}'", name), line });
+        }
+        let v = match self.vars.get(name) {
+            Some(Value::Real(_)) => v.promote(Type::Real),
+            _ => v,
+        };
+        self.vars.insert(name.to_string(), v);
+        Ok(v)
+    }
+}
+
+impl Index<&str> for Env {
+    type Output = Value;
+
+    fn index(&self, name: &str) -> &Value {
+        &self.vars[name]
+    }
+}
+
+// What to do after a statement: carry on, or leave the enclosing loop.
+enum Flow {
+    Next,
+    Exit,
+}
+
+// What a read prints before each value, unless told otherwise.
+pub const DEFAULT_PROMPT: &str = "? ";
+
+// Run a program whose read statements take their input from stdin.
+pub fn run(stmts: &[Stmt], env: &mut Env) -> Result<(), RuntimeError> {
+    run_with_prompt(stmts, env, DEFAULT_PROMPT)
+}
+
+// Like run(), but with prompt in place of the default, should stdin be
+// a terminal.
+pub fn run_with_prompt(stmts: &[Stmt], env: &mut Env, prompt: &str) -> Result<(), RuntimeError> {
+    let stdin = io::stdin();
+    let prompt = if stdin.is_terminal() { Some(prompt) } else { None };
+    exec_list(stmts, env, &mut stdin.lock(), &mut io::stdout().lock(), prompt)?;
+    Ok(())
+}
+
+// Like run(), but read statements take their input from input, a line
+// per value.
+pub fn run_with_input(stmts: &[Stmt], env: &mut Env, input: &mut dyn BufRead)
+        -> Result<(), RuntimeError> {
+    run_with_io(stmts, env, input, &mut io::stdout().lock())
+}
+
+// Like run_with_input(), but write statements print to output.
+pub fn run_with_io(stmts: &[Stmt], env: &mut Env, input: &mut dyn BufRead,
+        output: &mut dyn Write) -> Result<(), RuntimeError> {
+    exec_list(stmts, env, input, output, None)?;
+    Ok(())
+}
+
+// Like run_with_io(), but each read prints prompt to output first, as if
+// input were a terminal.
+pub fn run_interactive(stmts: &[Stmt], env: &mut Env, input: &mut dyn BufRead,
+        output: &mut dyn Write, prompt: &str) -> Result<(), RuntimeError> {
+    exec_list(stmts, env, input, output, Some(prompt))?;
+    Ok(())
+}
+
+fn exec_list(stmts: &[Stmt], env: &mut Env, input: &mut dyn BufRead, output: &mut dyn Write,
+        prompt: Option<&str>) -> Result<Flow, RuntimeError> {
+    for stmt in stmts {
+        if let Flow::Exit = exec(stmt, env, input, output, prompt)? {
+            return Ok(Flow::Exit);
+        }
+    }
+    Ok(Flow::Next)
+}
+
+fn exec(stmt: &Stmt, env: &mut Env, input: &mut dyn BufRead, output: &mut dyn Write,
+        prompt: Option<&str>) -> Result<Flow, RuntimeError> {
+    match *stmt {
+        Stmt::Assign { ref name, ref expr, .. } => {
+            let v = eval(expr, env)?;
+            env.assign(name, v, stmt.line())?;
+        }
+        Stmt::Decl { tp, ref name, ref expr, .. } => {
+            let v = match *expr {
+                Some(ref expr) => eval(expr, env)?,
+                None => Value::Int(0),
+            }.promote(tp);
+            env.insert(name.clone(), v);
+        }
+        Stmt::Const { tp, ref name, ref expr, .. } => {
+            let v = eval(expr, env)?.promote(tp);
+            env.set_const(name.clone(), v);
+        }
+        Stmt::Read { tp, ref names, .. } => {
+            let line = stmt.line();
+            for name in names {
+                if let Some(prompt) = prompt {
+                    write!(output, "{}", prompt).and_then(|_| output.flush())
+                        .map_err(|e| RuntimeError {
+                            message: format!("can't write output: {}", e), line })?;
+                }
+                // a typed read declares the variable; an untyped one
+                // stores into it and keeps its current type
+                match tp {
+                    Some(tp) => env.insert(name.clone(), read_value(input, Some(tp), line)?),
+                    None => {
+                        let tp = match env.get(name) {
+                            Some(Value::Int(_)) => Some(Type::Int),
+                            Some(Value::Real(_)) => Some(Type::Real),
+                            None => None,
+                        };
+                        env.assign(name, read_value(input, tp, line)?, line)?;
+                    }
+                }
+            }
+        }
+        // the items on one line, separated by spaces
+        Stmt::Write { ref args, .. } => {
+            let mut items = Vec::new();
+            for arg in args {
+                items.push(match *arg {
+                    WriteArg::Expr(ref expr) => format_value(&eval(expr, env)?),
+                    WriteArg::Str(ref s, _) => s.clone(),
+                });
+            }
+            writeln!(output, "{}", items.join(" ")).map_err(|e| RuntimeError {
+                message: format!("can't write output: {}", e), line: stmt.line() })?;
+        }
+        // the first branch whose condition holds, if any, else the else
+        Stmt::If { ref branches, ref else_body, .. } => {
+            for (cond, body) in branches {
+                if test(cond, env)? {
+                    return exec_list(body, env, input, output, prompt);
+                }
+            }
+            if let Some(ref else_body) = *else_body {
+                return exec_list(else_body, env, input, output, prompt);
+            }
+        }
+        Stmt::Do { ref body, .. } => {
+            while let Flow::Next = exec_list(body, env, input, output, prompt)? {}
+        }
+        Stmt::For { ref var, ref from, ref to, ref body, .. } => {
+            let bound = |v: Value| match v {
+                Value::Int(i) => Ok(i),
+                Value::Real(_) => Err(RuntimeError {
+                    message: format!("for loop bound {} is not an int", v), line: stmt.line() }),
+            };
+            let from = bound(eval(from, env)?)?;
+            let to = bound(eval(to, env)?)?;
+            for i in from..=to {
+                env.insert(var.clone(), Value::Int(i));
+                if let Flow::Exit = exec_list(body, env, input, output, prompt)? {
+                    break;
+                }
+            }
+        }
+        Stmt::Check { ref cond, .. } => {
+            if !test(cond, env)? {
+                return Ok(Flow::Exit);
+            }
+        }
+        Stmt::Assert { ref cond, .. } => {
+            if !test(cond, env)? {
+                return Err(RuntimeError { message: "assertion failed".to_string(),
+                    line: stmt.line() });
+            }
+        }
+    }
+    Ok(Flow::Next)
+}
+
+// Read one line of input and convert it to tp; with no type, take
+// whatever the text looks like.  White space around the number is
+// ignored, and it may have a sign: " -5 " reads as an int.
+fn read_value(input: &mut dyn BufRead, tp: Option<Type>, line: usize)
+        -> Result<Value, RuntimeError> {
+    let mut buf = String::new();
+    let count = input.read_line(&mut buf).map_err(|e| RuntimeError {
+        message: format!("can't read input: {}", e), line })?;
+    if count == 0 {
+        return Err(RuntimeError { message: "unexpected end of input".to_string(), line });
+    }
+    let text = buf.trim();
+    let bad = |what: &str| RuntimeError {
+        message: format!("invalid {} input '{}'", what, text), line };
+    match tp {
+        Some(Type::Int) => text.parse().map(Value::Int).map_err(|_| bad("integer")),
+        Some(Type::Real) => text.parse().map(Value::Real).map_err(|_| bad("real")),
+        None => text.parse().map(Value::Int)
+            .or_else(|_| text.parse().map(Value::Real))
+            .map_err(|_| bad("numeric")),
+    }
+}
+
+fn test(cond: &Cond, env: &mut Env) -> Result<bool, RuntimeError> {
+    let lhs = eval(&cond.lhs, env)?;
+    let rhs = eval(&cond.rhs, env)?;
+    let ord = match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => a.partial_cmp(&b),
+        (a, b) => a.as_real().partial_cmp(&b.as_real()),
+    };
+    Ok(match cond.op {
+        CompOp::Eq => ord == Some(Equal),
+        CompOp::Ne => ord != Some(Equal),
+        CompOp::Lt => ord == Some(Less),
+        CompOp::Gt => ord == Some(Greater),
+        CompOp::Le => ord == Some(Less) || ord == Some(Equal),
+        CompOp::Ge => ord == Some(Greater) || ord == Some(Equal),
+    })
+}
+
+pub fn eval(expr: &Expr, env: &mut Env) -> Result<Value, RuntimeError> {
+    match *expr {
+        Expr::Ident { ref name, span } => env.get(name).ok_or_else(|| RuntimeError {
+            message: format!("use of undefined variable '{}'", name), line: span.start_line }),
+        Expr::ILit(i, _) => Ok(Value::Int(i)),
+        Expr::RLit(r, _) => Ok(Value::Real(r)),
+        Expr::Neg { ref operand, span } => match eval(operand, env)? {
+            Value::Int(i) => {
+                i.checked_neg().map(Value::Int).ok_or_else(|| overflow(span.start_line))
+            }
+            Value::Real(r) => Ok(Value::Real(-r)),
+        },
+        Expr::Trunc(ref operand, _) => Ok(match eval(operand, env)? {
+            Value::Real(r) => Value::Int(r.trunc() as i64),
+            v => v,
+        }),
+        Expr::Float(ref operand, _) => Ok(Value::Real(eval(operand, env)?.as_real())),
+        // errors are reported on the operator's line, not the operands'
+        Expr::Binary { op, ref lhs, ref rhs, op_span, .. } => {
+            let lhs = eval(lhs, env)?;
+            let rhs = eval(rhs, env)?;
+            binary(op, lhs, rhs, op_span.start_line)
+        }
+        Expr::Assign { ref name, ref value, span } => {
+            let v = eval(value, env)?;
+            env.assign(name, v, span.start_line)
+        }
+        // only the branch taken is evaluated
+        Expr::Ternary { ref cond, ref then, ref otherwise, .. } => {
+            if test(cond, env)? { eval(then, env) } else { eval(otherwise, env) }
+        }
+    }
+}
+
+fn binary(op: BinOp, lhs: Value, rhs: Value, line: usize) -> Result<Value, RuntimeError> {
+    match (lhs, rhs) {
+        (Value::Int(_), Value::Int(0)) if op == BinOp::Div || op == BinOp::Mod => {
+            Err(RuntimeError { message: "division by zero".to_string(), line })
+        }
+        (Value::Int(0), Value::Int(b)) if op == BinOp::Pow && b < 0 => {
+            Err(RuntimeError { message: "division by zero".to_string(), line })
+        }
+        // only if the program wasn't checked first
+        (Value::Real(_), _) | (_, Value::Real(_)) if op.is_bitwise() => {
+            Err(RuntimeError { message: "bitwise operator on a real".to_string(), line })
+        }
+        (Value::Int(a), Value::Int(b)) => match op {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div => a.checked_div(b),     // i64::MIN / -1
+            BinOp::Mod => a.checked_rem(b),
+            BinOp::Pow => int_pow(a, b),
+            BinOp::BitAnd => Some(a & b),
+            BinOp::BitOr => Some(a | b),
+            BinOp::BitXor => Some(a ^ b),
+        }.map(Value::Int).ok_or_else(|| overflow(line)),
+        (a, b) => {
+            let (a, b) = (a.as_real(), b.as_real());
+            Ok(Value::Real(match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                BinOp::Mod => a % b,
+                BinOp::Pow => a.powf(b),
+                BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => unreachable!(),
+            }))
+        }
+    }
+}
+
+fn overflow(line: usize) -> RuntimeError {
+    RuntimeError { message: "integer overflow".to_string(), line }
+}
+
+// Like integer division, a negative power truncates toward zero:
+// 2 ** -1 is 1 / 2, which is 0.  None if the result doesn't fit in an
+// i64, or for 0 ** -n.  Bases 0, 1 and -1 take any exponent, however
+// large.
+pub fn int_pow(a: i64, b: i64) -> Option<i64> {
+    match a {
+        1 => Some(1),
+        -1 => Some(if b % 2 == 0 { 1 } else { -1 }),
+        0 if b > 0 => Some(0),
+        _ if b < 0 => 1i64.checked_div(a),
+        _ if b > u32::MAX as i64 => None,
+        _ => a.checked_pow(b as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse_src;
+
+    fn run_src(src: &str) -> Env {
+        let mut env = Env::new();
+        run(&parse_src(src), &mut env).unwrap();
+        env
+    }
+
+    #[test]
+    fn reals_always_have_a_decimal_point() {
+        assert_eq!(format_value(&Value::Int(3)), "3");
+        assert_eq!(format_value(&Value::Int(-42)), "-42");
+        assert_eq!(format_value(&Value::Real(3.0)), "3.0");
+        assert_eq!(format_value(&Value::Real(-0.0)), "-0.0");
+        assert_eq!(format_value(&Value::Real(2.5)), "2.5");
+        assert_eq!(format_value(&Value::Real(1e20)), "100000000000000000000.0");
+        assert_eq!(format_value(&Value::Real(f64::INFINITY)), "inf");
+        assert_eq!(format_value(&Value::Real(f64::NAN)), "NaN");
+        assert_eq!(Value::Real(7.0).to_string(), "7.0");
+    }
+
+    #[test]
+    fn arithmetic_and_types() {
+        let env = run_src("a := 7 / 2\nb := 7.0 / 2\nc := a * 2 + b\nd := -(a % 2)");
+        assert_eq!(env["a"], Value::Int(3));
+        assert_eq!(env["b"], Value::Real(3.5));
+        assert_eq!(env["c"], Value::Real(9.5));
+        assert_eq!(env["d"], Value::Int(-1));
+    }
+
+    #[test]
+    fn operators_associate_as_in_math() {
+        assert_eq!(output("write 8 - 2 - 1, 8 - 2 + 1, 64 / 4 / 2, 64 / 4 * 2"), "5 7 8 32\n");
+        assert_eq!(output("write 100 % 7 % 3, 3 * 7 % 4, 8.0 / 2 / 2"), "2 1 2.0\n");
+        assert_eq!(output("write 2 ** 3 ** 2, (2 ** 3) ** 2, 2 ** 2 ** -1"), "512 64 1\n");
+        // the right-hand side of a compound assignment is one operand
+        assert_eq!(output("int x := 8 x -= 2 - 1 write x x /= 4 / 2 write x"), "7\n3\n");
+    }
+
+    #[test]
+    fn mod_keyword() {
+        assert_eq!(output("write 17 mod 5, -17 mod 5, 7.5 mod 2"),
+            output("write 17 % 5, -17 % 5, 7.5 % 2"));
+        assert_eq!(output("int x := 17 write x mod 5"), "2\n");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(output("write 6 & 3"), "2\n");
+        assert_eq!(output("write 6 | 3, 6 ^ 3, -1 & 255, 1 + 2 & 6"), "7 5 255 2\n");
+        assert_eq!(output("int n := 12 n := n ^ n | 1 write n"), "1\n");
+    }
+
+    #[test]
+    fn powers() {
+        let env = run_src("a := 2 ** 3 ** 2\nb := -2 ** 3\nc := 2 ** -1\nd := 4.0 ** 0.5\n\
+            e := -1 ** -3");
+        assert_eq!(env["a"], Value::Int(512));
+        assert_eq!(env["b"], Value::Int(-8));
+        assert_eq!(env["c"], Value::Int(0));
+        assert_eq!(env["d"], Value::Real(2.0));
+        assert_eq!(env["e"], Value::Int(-1));
+        // exponents past u32::MAX
+        assert_eq!(output("write 0 ** 5000000000, 1 ** 5000000000, -1 ** 5000000001"),
+            "0 1 -1\n");
+        assert_eq!(run_err("write 2 ** 5000000000").message, "integer overflow");
+    }
+
+    #[test]
+    fn conversions() {
+        let env = run_src("a := trunc(-2.75)\nb := float(3) / 2.0");
+        assert_eq!(env["a"], Value::Int(-2));
+        assert_eq!(env["b"], Value::Real(1.5));
+    }
+
+    // What a write statement would print for each of its arguments.
+    fn written(src: &str) -> Vec<String> {
+        match parse_src(src).as_slice() {
+            [Stmt::Write { args, .. }] => args.iter().map(|arg| match *arg {
+                WriteArg::Expr(ref expr) => format_value(&eval(expr, &mut Env::new()).unwrap()),
+                WriteArg::Str(ref s, _) => s.clone(),
+            }).collect(),
+            stmts => panic!("not a single write: {:?}", stmts),
+        }
+    }
+
+    #[test]
+    fn conversions_in_write() {
+        assert_eq!(written("write trunc(3.9)"), vec!["3"]);
+        assert_eq!(written("write float(2) / 4"), vec!["0.5"]);
+        // toward zero, and a no-op on what's already the right type
+        assert_eq!(written("write trunc(-3.9), trunc(0.2), float(-7), trunc(2) + 1"),
+            vec!["-3", "0", "-7.0", "3"]);
+        // float keeps int division from happening
+        assert_eq!(written("write 2 / 4, float(2 / 4), float(2) / float(4)"),
+            vec!["0", "0.0", "0.5"]);
+    }
+
+    #[test]
+    fn do_loop_exits_on_failed_check() {
+        let env = run_src("int n := 0\ndo check n < 5 n := n + 1 od");
+        assert_eq!(env["n"], Value::Int(5));
+        assert_eq!(output("int n := 0 do check n < 3 n := n + 1 od write n"), "3\n");
+    }
+
+    #[test]
+    fn counting_loop_ends_at_failed_check() {
+        let env = run_src("int i := 0\nint sum := 0\ndo\n  check i < 5\n  i := i + 1\n\
+            sum := sum + i\nod");
+        assert_eq!((env["i"], env["sum"]), (Value::Int(5), Value::Int(15)));
+        // only the innermost loop is left
+        let env = run_src("int n := 0\nint i := 0\ndo check i < 3 i := i + 1\n\
+            int j := 0 do check j < i j := j + 1 n := n + 1 od od");
+        assert_eq!(env["n"], Value::Int(6));
+    }
+
+    #[test]
+    fn else_runs_when_condition_fails() {
+        assert_eq!(output("if 1 > 2 write 1 else write 2 fi"), "2\n");
+        assert_eq!(output("if 2 > 1 write 1 else write 2 fi"), "1\n");
+        let env = run_src("int n := 3\nint m := 0\nif n > 5 m := 1 else m := 2 fi");
+        assert_eq!(env["m"], Value::Int(2));
+    }
+
+    #[test]
+    fn conditional_expression() {
+        assert_eq!(output("int a := 3 int b := 5 write (a < b ? a : b), (a > b ? a : b)"),
+            "3 5\n");
+        // the other branch isn't evaluated, so can't fail
+        assert_eq!(output("int a := 0 write (a == 0 ? 1 : 1 / a) * 2"), "2\n");
+        let env = run_src("int n := 4\nint m := (n % 2 == 0 ? n / 2 : 3 * n + 1)");
+        assert_eq!(env["m"], Value::Int(2));
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        let env = run_src("int n := 1 n++ n++ n--\nreal r := 0.5 r++");
+        assert_eq!(env["n"], Value::Int(2));
+        assert_eq!(env["r"], Value::Real(1.5));
+        assert_eq!(output("int i := 0 do check i < 3 write i i++ od"), "0\n1\n2\n");
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let env = run_src("int n := 7 n += 3 n *= 2 + 1 n -= 2 n /= 4\nreal r := 1 r /= 4");
+        assert_eq!(env["n"], Value::Int(7));
+        assert_eq!(env["r"], Value::Real(0.25));
+    }
+
+    #[test]
+    fn declarations_start_at_zero() {
+        let env = run_src("int n\nreal r\nint m := n + 1");
+        assert_eq!(env["n"], Value::Int(0));
+        assert_eq!(env["r"], Value::Real(0.0));
+        assert_eq!(env["m"], Value::Int(1));
+        assert_eq!(output("real r\nwrite r\nr := 1.5\nwrite r"), "0.0\n1.5\n");
+    }
+
+    #[test]
+    fn assertions() {
+        let env = run_src("int x := 2\nassert x * 2 == 4\nx := 3");
+        assert_eq!(env["x"], Value::Int(3));
+        let err = run_err("int x := 2\nassert x < 5\nassert x > 2\nx := 3");
+        assert_eq!((err.message.as_str(), err.line), ("assertion failed", 3));
+        assert_eq!(err.to_string(), "runtime error on line 3: assertion failed");
+    }
+
+    #[test]
+    fn first_true_branch_runs() {
+        let src = "m := 0\nif n < 0 m := 1 elsif n < 5 m := 2 elsif n < 9 m := 3 else m := 4 fi";
+        let stmts = parse_src(src);
+        let mut env = Env::new();
+        for &(n, m) in &[(-1, 1), (3, 2), (4, 2), (5, 3), (9, 4)] {
+            env.insert("n".to_string(), Value::Int(n));
+            run(&stmts, &mut env).unwrap();
+            assert_eq!(env["m"], Value::Int(m));
+        }
+    }
+
+    #[test]
+    fn for_loop_is_inclusive() {
+        let env = run_src("int sum := 0\nfor i := 1 to 4 do sum := sum + i od");
+        assert_eq!(env["sum"], Value::Int(10));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let err = run(&parse_src("x := 1\ny := z"), &mut Env::new()).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn constants_reject_assignment() {
+        let env = run_src("const int k := 3\nx := k * 2");
+        assert_eq!(env["x"], Value::Int(6));
+        assert!(env.is_const("k") && !env.is_const("x"));
+        assert_eq!(run_err("const int k := 3\nk := k + 1").to_string(),
+            "runtime error on line 2: cannot assign to constant 'k'");
+        // a declaration replaces the constant with a variable
+        let env = run_src("const int k := 3\nint k := 4\nk := 5");
+        assert_eq!(env["k"], Value::Int(5));
+    }
+
+    #[test]
+    fn chained_assignment() {
+        let env = run_src("int a := 0\nint b := 0\na := b := 3");
+        assert_eq!((env["a"], env["b"]), (Value::Int(3), Value::Int(3)));
+        // right to left: b is stored before a's value is known
+        let env = run_src("int a := 1\nint b := 2\nint c := 0\na := b := c := b + 1");
+        assert_eq!((env["a"], env["b"], env["c"]), (Value::Int(3), Value::Int(3), Value::Int(3)));
+        let err = run_err("const int k := 1\nint b := 0\nb := k := 2");
+        assert_eq!(err.to_string(), "runtime error on line 3: cannot assign to constant 'k'");
+    }
+
+    #[test]
+    fn ints_stored_in_reals_are_promoted() {
+        let env = run_src("real r := 1\nconst real half := 1 / 2\nint i := 3\nreal s := 0.0\n\
+            s := i + 1\nreal t := i + 0.5");
+        assert_eq!(env["r"], Value::Real(1.0));
+        assert_eq!(env["half"], Value::Real(0.0));    // int division, then promotion
+        assert_eq!(env["s"], Value::Real(4.0));
+        assert_eq!(env["t"], Value::Real(3.5));
+        // a real variable stays real, so later division isn't int division
+        let env = run_src("real r := 0.0\nr := 7\nr := r / 2");
+        assert_eq!(env["r"], Value::Real(3.5));
+        let env = run_src("int a := 0\nreal b := 0.0\nb := a := 2");
+        assert_eq!((env["a"], env["b"]), (Value::Int(2), Value::Real(2.0)));
+    }
+
+    fn run_input(src: &str, input: &str) -> Result<Env, RuntimeError> {
+        let mut env = Env::new();
+        run_with_input(&parse_src(src), &mut env, &mut input.as_bytes())?;
+        Ok(env)
+    }
+
+    // What running src prints.
+    fn output(src: &str) -> String {
+        let mut out = Vec::new();
+        run_with_io(&parse_src(src), &mut Env::new(), &mut io::empty(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_puts_its_items_on_one_line() {
+        assert_eq!(output("write 1, 2, 3"), "1 2 3\n");
+        assert_eq!(output("int a := 4\nwrite a"), "4\n");
+        assert_eq!(output("int a := 4 write a, a + 1.5, \"done\" write 2"), "4 5.5 done\n2\n");
+    }
+
+    #[test]
+    fn read_takes_a_line_per_value() {
+        let env = run_input("read int x", "7\n").unwrap();
+        assert_eq!(env["x"], Value::Int(7));
+        let env = run_input("read real r\nread int a, b\nread c\nread d", " 2 \n-3\n4\n5\n1.5")
+            .unwrap();
+        assert_eq!(env["r"], Value::Real(2.0));
+        assert_eq!((env["a"], env["b"]), (Value::Int(-3), Value::Int(4)));
+        // untyped reads of new variables go by what the text looks like
+        assert_eq!((env["c"], env["d"]), (Value::Int(5), Value::Real(1.5)));
+        assert_eq!(run_input("read int x", "abc\n").unwrap_err().to_string(),
+            "runtime error on line 1: invalid integer input 'abc'");
+        assert_eq!(run_input("read int x\nread real y", "1\n2.x\n").unwrap_err().to_string(),
+            "runtime error on line 2: invalid real input '2.x'");
+        assert_eq!(run_input("read int x", "").unwrap_err().to_string(),
+            "runtime error on line 1: unexpected end of input");
+    }
+
+    #[test]
+    fn read_takes_a_sign() {
+        let env = run_input("read int x\nread real y", "-5\n3.14\n").unwrap();
+        assert_eq!(env["x"], Value::Int(-5));
+        assert_eq!(format_value(&env["y"]), "3.14");
+        let env = run_input("read int a\nread real b\nread c, d", "  +7\t\n-0.5\n-2\n+1.5\n")
+            .unwrap();
+        assert_eq!((env["a"], env["b"]), (Value::Int(7), Value::Real(-0.5)));
+        assert_eq!((env["c"], env["d"]), (Value::Int(-2), Value::Real(1.5)));
+        // but only one number, and an int has no fraction
+        assert_eq!(run_input("int n\nread int x", "1.2\n").unwrap_err().to_string(),
+            "runtime error on line 2: invalid integer input '1.2'");
+        assert_eq!(run_input("read int x", "- 5\n").unwrap_err().to_string(),
+            "runtime error on line 1: invalid integer input '- 5'");
+        assert_eq!(run_input("read real y", "--1\n").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn read_prompts_only_when_interactive() {
+        let src = "read int x, y\ndo check x > 0 read z x-- od\nwrite y";
+        let stmts = parse_src(src);
+        let mut out = Vec::new();
+        run_interactive(&stmts, &mut Env::new(), &mut "2\n5\n0\n0\n".as_bytes(), &mut out,
+            DEFAULT_PROMPT).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "? ? ? ? 5\n");
+        // as from a pipe
+        let mut out = Vec::new();
+        run_with_io(&stmts, &mut Env::new(), &mut "1\n5\n0\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "5\n");
+    }
+
+    fn run_err(src: &str) -> RuntimeError {
+        run(&parse_src(src), &mut Env::new()).unwrap_err()
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let err = run_err("write 5 / 0");
+        assert_eq!(err.to_string(), "runtime error on line 1: division by zero");
+        // the line is the operator's, not the statement's
+        assert_eq!(run_err("x := 0\nwrite 1 +\n  7 %\n  x").line, 3);
+        assert_eq!(run_err("write 0 ** -2").message, "division by zero");
+        let env = run_src("a := 1.0 / 0\nb := -1 / 0.0");
+        assert_eq!(env["a"], Value::Real(f64::INFINITY));
+        assert_eq!(env["b"], Value::Real(f64::NEG_INFINITY));
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let err = run_err("write 9223372036854775807 + 1");
+        assert_eq!(err.to_string(), "runtime error on line 1: integer overflow");
+        assert_eq!(run_err("x := 3037000500\nx := x *\n  x").line, 2);
+        let min = "m := 0 - 9223372036854775807 - 1\n";
+        assert_eq!(run_src(min)["m"], Value::Int(i64::MIN));
+        assert_eq!(run_err(&format!("{}x := 1\nx := -m", min)).line, 3);
+        assert_eq!(run_err(&format!("{}x := m / -1", min)).message, "integer overflow");
+        assert_eq!(run_err(&format!("{}x := m % -1", min)).message, "integer overflow");
+        assert_eq!(run_err("x := 2 ** 63").message, "integer overflow");
+        assert_eq!(run_src("x := 2 ** 62 - 1 + 2 ** 62")["x"], Value::Int(i64::MAX));
+        // reals don't overflow
+        assert_eq!(run_src("r := 10.0 ** 200 * 10.0 ** 200")["r"], Value::Real(f64::INFINITY));
+    }
+}
@@ -0,0 +1,179 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Constant folding
+//
+//  Replaces each subexpression whose operands are all literals with the
+//  literal it evaluates to, using the interpreter's rules: int operators
+//  stay int (so 7 / 2 is 3), and mixing in a real makes the result real.
+//  Anything that mentions a variable is left alone, as is anything that
+//  would fail or misbehave at run time -- division by zero, int overflow,
+//  or a real that isn't finite -- so the error still happens when the
+//  program runs.  A folded literal keeps the span of the expression it
+//  replaces.
+//
+
+use crate::ast::*;
+use crate::eval::int_pow;
+
+pub fn fold_constants(expr: Expr) -> Expr {
+    match expr {
+        Expr::Neg { operand, span } => match fold_constants(*operand) {
+            Expr::ILit(i, _) if i != i64::MIN => Expr::ILit(-i, span),
+            Expr::RLit(r, _) => Expr::RLit(-r, span),
+            operand => Expr::Neg { operand: Box::new(operand), span },
+        },
+        Expr::Trunc(operand, span) => match fold_constants(*operand) {
+            Expr::RLit(r, _) if r.is_finite() && r.trunc().abs() < i64::MAX as f64 => {
+                Expr::ILit(r.trunc() as i64, span)
+            }
+            operand => Expr::Trunc(Box::new(operand), span),
+        },
+        Expr::Float(operand, span) => match fold_constants(*operand) {
+            Expr::ILit(i, _) => Expr::RLit(i as f64, span),
+            Expr::RLit(r, _) => Expr::RLit(r, span),
+            operand => Expr::Float(Box::new(operand), span),
+        },
+        Expr::Binary { op, lhs, rhs, span, op_span } => {
+            let lhs = fold_constants(*lhs);
+            let rhs = fold_constants(*rhs);
+            binary(op, &lhs, &rhs, span).unwrap_or_else(|| Expr::binary(op, lhs, rhs, op_span))
+        }
+        Expr::Assign { name, value, span } => {
+            Expr::Assign { name, value: Box::new(fold_constants(*value)), span }
+        }
+        Expr::Ternary { cond: c, then, otherwise, span } => Expr::Ternary {
+            cond: Box::new(cond(*c)),
+            then: Box::new(fold_constants(*then)),
+            otherwise: Box::new(fold_constants(*otherwise)),
+            span,
+        },
+        expr => expr,
+    }
+}
+
+// Folds every expression in a program, including conditions, write
+// arguments, and for-loop bounds.
+pub fn fold_program(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts.into_iter().map(stmt).collect()
+}
+
+fn stmt(stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Assign { name, expr, span } => Stmt::Assign { name, expr: fold_constants(expr), span },
+        Stmt::Decl { tp, name, expr, span } => {
+            Stmt::Decl { tp, name, expr: expr.map(fold_constants), span }
+        }
+        Stmt::Const { tp, name, expr, span } => {
+            Stmt::Const { tp, name, expr: fold_constants(expr), span }
+        }
+        Stmt::Write { args, span } => Stmt::Write {
+            args: args.into_iter().map(|arg| match arg {
+                WriteArg::Expr(expr) => WriteArg::Expr(fold_constants(expr)),
+                arg => arg,
+            }).collect(),
+            span,
+        },
+        Stmt::If { branches, else_body, span } => Stmt::If {
+            branches: branches.into_iter()
+                .map(|(cond, body)| (self::cond(cond), fold_program(body)))
+                .collect(),
+            else_body: else_body.map(fold_program),
+            span,
+        },
+        Stmt::Do { body, span } => Stmt::Do { body: fold_program(body), span },
+        Stmt::For { var, from, to, body, span } => Stmt::For {
+            var,
+            from: fold_constants(from),
+            to: fold_constants(to),
+            body: fold_program(body),
+            span,
+        },
+        Stmt::Check { cond, span } => Stmt::Check { cond: self::cond(cond), span },
+        Stmt::Assert { cond, span } => Stmt::Assert { cond: self::cond(cond), span },
+        stmt @ Stmt::Read { .. } => stmt,
+    }
+}
+
+fn cond(cond: Cond) -> Cond {
+    Cond { lhs: fold_constants(cond.lhs), op: cond.op, rhs: fold_constants(cond.rhs) }
+}
+
+// The literal lhs op rhs evaluates to, if both are literals and the
+// result is safe to compute now.
+fn binary(op: BinOp, lhs: &Expr, rhs: &Expr, span: Span) -> Option<Expr> {
+    match (lhs, rhs) {
+        (&Expr::ILit(a, _), &Expr::ILit(b, _)) => match op {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div => a.checked_div(b),
+            BinOp::Mod => a.checked_rem(b),
+            BinOp::Pow => int_pow(a, b),
+            BinOp::BitAnd => Some(a & b),
+            BinOp::BitOr => Some(a | b),
+            BinOp::BitXor => Some(a ^ b),
+        }.map(|i| Expr::ILit(i, span)),
+        // a type error, left for check() to report
+        _ if op.is_bitwise() => None,
+        (&Expr::ILit(..), &Expr::RLit(..)) | (&Expr::RLit(..), &Expr::ILit(..))
+        | (&Expr::RLit(..), &Expr::RLit(..)) => {
+            let (a, b) = (real(lhs), real(rhs));
+            let r = match op {
+                BinOp::Add => a + b,
+                BinOp::Sub => a - b,
+                BinOp::Mul => a * b,
+                BinOp::Div => a / b,
+                BinOp::Mod => a % b,
+                BinOp::Pow => a.powf(b),
+                BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => unreachable!(),
+            };
+            if r.is_finite() { Some(Expr::RLit(r, span)) } else { None }
+        }
+        _ => None,
+    }
+}
+
+fn real(lit: &Expr) -> f64 {
+    match *lit {
+        Expr::ILit(i, _) => i as f64,
+        Expr::RLit(r, _) => r,
+        _ => unreachable!("only called on literals"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse_src;
+    use crate::pretty::pretty_print;
+
+    fn fold(src: &str) -> String {
+        pretty_print(&fold_program(parse_src(src)))
+    }
+
+    #[test]
+    fn folds_literal_subexpressions() {
+        assert_eq!(fold("x := 2 + 3 * 4"), "x := 14\n");
+        assert_eq!(fold("x := 7 / 2 + 7.0 / 2"), "x := 6.5\n");
+        assert_eq!(fold("x := trunc(3.9) + 2 ** 3 ** 2"), "x := 515\n");
+        assert_eq!(fold("x := float(3) - -1"), "x := 4.0\n");
+        assert_eq!(fold("x := 6 & 3 | 8 ^ 1"), "x := 11\n");
+        assert_eq!(fold("x := 2 ** -1 % 5"), "x := 0\n");
+        assert_eq!(fold("if a < 1 + 1 write 2 * 3, \"s\" fi"), "if a < 2\n  write 6, \"s\"\nfi\n");
+        assert_eq!(fold("for i := 0 - 1 to 2 * 5 do check i != 3 + 4 od"),
+            "for i := -1 to 10 do\n  check i != 7\nod\n");
+    }
+
+    #[test]
+    fn leaves_variables_and_failures_alone() {
+        assert_eq!(fold("x := a + 2 * 3"), "x := a + 6\n");
+        // (a + 2) + 3 isn't a + (2 + 3) in general, so nothing to fold
+        assert_eq!(fold("x := a + 2 + 3"), "x := a + 2 + 3\n");
+        assert_eq!(fold("x := trunc(a) * (1 + 1)"), "x := trunc(a) * 2\n");
+        assert_eq!(fold("x := 1 / (2 - 2)"), "x := 1 / 0\n");
+        assert_eq!(fold("x := 4 % 0 + 1"), "x := 4 % 0 + 1\n");
+        assert_eq!(fold("x := 9223372036854775807 + 1"), "x := 9223372036854775807 + 1\n");
+        assert_eq!(fold("x := 10 ** 100"), "x := 10 ** 100\n");
+        assert_eq!(fold("x := 1.0 / 0"), "x := 1.0 / 0\n");
+        assert_eq!(fold("x := 6.0 & 3"), "x := 6.0 & 3\n");
+    }
+}
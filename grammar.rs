@@ -0,0 +1,285 @@
+///////////////////////////////////////////////////////////////////////////////
+//  The grammar, as data
+//
+//  The productions the parser predicts, named as in its trace, and the
+//  FIRST and FOLLOW sets computed from them by the usual fixpoint
+//  iterations.  The parser's match arms are written out by hand; its
+//  tests check them against these sets.
+//
+//  Where the parser needs its second token of lookahead (a statement
+//  that starts with an identifier, and assign_value), the grammar isn't
+//  LL(1), but FIRST and FOLLOW are defined all the same.  The end
+//  marker $$ is TokTp::End, matched explicitly by program, so nothing
+//  follows program itself.
+//
+//  Operators, from loosest to tightest binding:
+//      & | ^       bit_op, in bit_tail: left-associative, ints only
+//      + -         add_op, in term_tail: left-associative
+//      * / %       mul_op, in factor_tail: left-associative
+//      **          in power_tail: right-associative
+//      - (unary)   in factor, so tighter than all of them: -2 ** 2 is 4
+//  The tails are right-recursive, as LL(1) needs, but the parser builds
+//  the tree for the left-associative ones by passing the operand so far
+//  down into the tail, so 8 - 2 - 1 is (8 - 2) - 1.  power_tail recurses
+//  through power instead, so 2 ** 3 ** 2 is 2 ** (3 ** 2).  Comparisons
+//  (comp_op) don't associate at all: a condition has exactly one, and
+//  its operands are whole exprs, so check n & 1 == 1 tests n & 1.
+//
+
+use crate::scanner::TokTp;
+use std::collections::{HashMap, HashSet};
+
+use self::Symbol::{N, T};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    T(TokTp),
+    N(&'static str),
+}
+
+// lhs --> rhs; an empty rhs is epsilon.
+#[derive(Debug)]
+pub struct Production {
+    pub lhs: &'static str,
+    pub rhs: &'static [Symbol],
+}
+
+const fn p(lhs: &'static str, rhs: &'static [Symbol]) -> Production {
+    Production { lhs, rhs }
+}
+
+// The start symbol comes first.
+pub const PRODUCTIONS: &[Production] = &[
+    p("program", &[N("stmt_list"), T(TokTp::End)]),
+    p("stmt_list", &[N("stmt"), N("stmt_list")]),
+    p("stmt_list", &[T(TokTp::Semi), N("stmt_list")]),
+    p("stmt_list", &[T(TokTp::LBrace), N("stmt_list"), T(TokTp::RBrace), N("stmt_list")]),
+    p("stmt_list", &[]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::Gets), N("assign_value")]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::Incr)]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::Decr)]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::PlusEq), N("expr")]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::MinusEq), N("expr")]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::TimesEq), N("expr")]),
+    p("stmt", &[T(TokTp::Ident), T(TokTp::DivEq), N("expr")]),
+    p("stmt", &[T(TokTp::Read), N("type"), T(TokTp::Ident), N("ident_tail")]),
+    p("stmt", &[T(TokTp::Write), N("write_arg"), N("write_arg_tail")]),
+    p("stmt", &[T(TokTp::If), N("comp"), N("stmt_list"), N("elsif_part"), N("else_part"),
+        T(TokTp::Fi)]),
+    p("stmt", &[T(TokTp::Do), N("stmt_list"), T(TokTp::Od)]),
+    p("stmt", &[T(TokTp::For), T(TokTp::Ident), T(TokTp::Gets), N("expr"), T(TokTp::To),
+        N("expr"), T(TokTp::Do), N("stmt_list"), T(TokTp::Od)]),
+    p("stmt", &[T(TokTp::Check), N("comp")]),
+    p("stmt", &[T(TokTp::Assert), N("comp")]),
+    p("stmt", &[T(TokTp::Int), T(TokTp::Ident), N("init_part")]),
+    p("stmt", &[T(TokTp::Real), T(TokTp::Ident), N("init_part")]),
+    // the parser insists on int or real here
+    p("stmt", &[T(TokTp::Const), N("type"), T(TokTp::Ident), T(TokTp::Gets), N("expr")]),
+    p("type", &[T(TokTp::Int)]),
+    p("type", &[T(TokTp::Real)]),
+    p("type", &[]),
+    p("init_part", &[T(TokTp::Gets), N("expr")]),
+    p("init_part", &[]),
+    p("ident_tail", &[T(TokTp::Comma), T(TokTp::Ident), N("ident_tail")]),
+    p("ident_tail", &[]),
+    p("write_arg", &[T(TokTp::SLit)]),
+    p("write_arg", &[N("expr")]),
+    p("write_arg_tail", &[T(TokTp::Comma), N("write_arg"), N("write_arg_tail")]),
+    p("write_arg_tail", &[]),
+    p("elsif_part", &[T(TokTp::Elsif), N("comp"), N("stmt_list"), N("elsif_part")]),
+    p("elsif_part", &[]),
+    p("else_part", &[T(TokTp::Else), N("stmt_list")]),
+    p("else_part", &[]),
+    p("assign_value", &[T(TokTp::Ident), T(TokTp::Gets), N("assign_value")]),
+    p("assign_value", &[N("expr")]),
+    p("comp", &[N("expr"), N("comp_op"), N("expr")]),
+    p("expr", &[N("sum"), N("bit_tail")]),
+    p("bit_tail", &[N("bit_op"), N("sum"), N("bit_tail")]),
+    p("bit_tail", &[]),
+    p("sum", &[N("term"), N("term_tail")]),
+    p("term_tail", &[N("add_op"), N("term"), N("term_tail")]),
+    p("term_tail", &[]),
+    p("term", &[N("power"), N("factor_tail")]),
+    p("factor_tail", &[N("mul_op"), N("power"), N("factor_tail")]),
+    p("factor_tail", &[]),
+    p("power", &[N("factor"), N("power_tail")]),
+    p("power_tail", &[T(TokTp::Pow), N("power")]),
+    p("power_tail", &[]),
+    p("factor", &[T(TokTp::Ident)]),
+    p("factor", &[T(TokTp::ILit)]),
+    p("factor", &[T(TokTp::RLit)]),
+    p("factor", &[T(TokTp::LParen), N("expr"), N("cond_tail"), T(TokTp::RParen)]),
+    p("factor", &[T(TokTp::Minus), N("factor")]),
+    p("factor", &[T(TokTp::Trunc), T(TokTp::LParen), N("expr"), T(TokTp::RParen)]),
+    p("factor", &[T(TokTp::Float), T(TokTp::LParen), N("expr"), T(TokTp::RParen)]),
+    p("cond_tail", &[N("comp_op"), N("expr"), T(TokTp::Question), N("expr"), T(TokTp::Colon),
+        N("expr")]),
+    p("cond_tail", &[]),
+    p("comp_op", &[T(TokTp::Greater)]),
+    p("comp_op", &[T(TokTp::Lesser)]),
+    p("comp_op", &[T(TokTp::EqualTo)]),
+    p("comp_op", &[T(TokTp::NEqualTo)]),
+    p("comp_op", &[T(TokTp::GreaterEq)]),
+    p("comp_op", &[T(TokTp::LesserEq)]),
+    p("add_op", &[T(TokTp::Plus)]),
+    p("add_op", &[T(TokTp::Minus)]),
+    p("mul_op", &[T(TokTp::Times)]),
+    p("mul_op", &[T(TokTp::DivBy)]),
+    p("mul_op", &[T(TokTp::Mod)]),
+    p("bit_op", &[T(TokTp::BitAnd)]),
+    p("bit_op", &[T(TokTp::BitOr)]),
+    p("bit_op", &[T(TokTp::BitXor)]),
+];
+
+type Sets = HashMap<&'static str, HashSet<TokTp>>;
+
+// Whether nt can derive the empty string.
+pub fn nullable(nt: &str) -> bool {
+    check(nt);
+    first_sets().1.contains(nt)
+}
+
+// The tokens that can begin a string derived from nt.
+pub fn first(nt: &str) -> HashSet<TokTp> {
+    check(nt);
+    first_sets().0.remove(nt).unwrap_or_default()
+}
+
+// The tokens that can come right after nt in a program.
+pub fn follow(nt: &str) -> HashSet<TokTp> {
+    check(nt);
+    follow_sets().remove(nt).unwrap_or_default()
+}
+
+fn check(nt: &str) {
+    assert!(PRODUCTIONS.iter().any(|p| p.lhs == nt), "no nonterminal named {}", nt);
+}
+
+// FIRST of every nonterminal, and the set of those that are nullable,
+// grown until nothing changes.
+fn first_sets() -> (Sets, HashSet<&'static str>) {
+    let mut first = Sets::new();
+    let mut nullable = HashSet::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for p in PRODUCTIONS {
+            let (tokens, empty) = first_of(p.rhs, &first, &nullable);
+            let set = first.entry(p.lhs).or_default();
+            let before = set.len();
+            set.extend(tokens);
+            changed |= set.len() != before;
+            if empty && nullable.insert(p.lhs) {
+                changed = true;
+            }
+        }
+    }
+    (first, nullable)
+}
+
+// FIRST of a string of symbols, and whether it's nullable.
+fn first_of(symbols: &[Symbol], first: &Sets, nullable: &HashSet<&str>)
+        -> (HashSet<TokTp>, bool) {
+    let mut tokens = HashSet::new();
+    for symbol in symbols {
+        match *symbol {
+            T(tp) => {
+                tokens.insert(tp);
+                return (tokens, false);
+            }
+            N(nt) => {
+                if let Some(set) = first.get(nt) {
+                    tokens.extend(set.iter().copied());
+                }
+                if !nullable.contains(nt) {
+                    return (tokens, false);
+                }
+            }
+        }
+    }
+    (tokens, true)
+}
+
+// For each nonterminal B in A --> x B y, FOLLOW(B) takes in FIRST(y),
+// and FOLLOW(A) too if y is nullable.
+fn follow_sets() -> Sets {
+    let (first, nullable) = first_sets();
+    let mut follow = Sets::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for p in PRODUCTIONS {
+            for (i, symbol) in p.rhs.iter().enumerate() {
+                let nt = match *symbol {
+                    N(nt) => nt,
+                    T(_) => continue,
+                };
+                let (mut tokens, empty) = first_of(&p.rhs[i + 1..], &first, &nullable);
+                if empty {
+                    if let Some(set) = follow.get(p.lhs) {
+                        tokens.extend(set.iter().copied());
+                    }
+                }
+                let set = follow.entry(nt).or_default();
+                let before = set.len();
+                set.extend(tokens);
+                changed |= set.len() != before;
+            }
+        }
+    }
+    follow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(tps: &[TokTp]) -> HashSet<TokTp> {
+        tps.iter().copied().collect()
+    }
+
+    #[test]
+    fn every_nonterminal_has_a_production() {
+        for p in PRODUCTIONS {
+            for symbol in p.rhs {
+                if let N(nt) = *symbol {
+                    assert!(PRODUCTIONS.iter().any(|p| p.lhs == nt), "{} undefined", nt);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn computes_first() {
+        assert_eq!(first("add_op"), set(&[TokTp::Plus, TokTp::Minus]));
+        // through power and factor
+        assert_eq!(first("term"), first("factor"));
+        assert_eq!(first("factor_tail"), set(&[TokTp::Times, TokTp::DivBy, TokTp::Mod]));
+        assert_eq!(first("type"), set(&[TokTp::Int, TokTp::Real]));
+        // stmt_list is nullable, so program can start with $$
+        assert!(first("program").contains(&TokTp::End));
+        assert!(nullable("stmt_list") && nullable("cond_tail") && !nullable("stmt"));
+    }
+
+    #[test]
+    fn computes_follow() {
+        assert!(follow("program").is_empty());
+        assert_eq!(follow("cond_tail"), set(&[TokTp::RParen]));
+        assert_eq!(follow("type"), set(&[TokTp::Ident]));
+        assert_eq!(follow("else_part"), set(&[TokTp::Fi]));
+        assert_eq!(follow("bit_tail"), follow("expr"));
+        assert_eq!(follow("term_tail"), follow("sum"));
+        // a term is followed by whatever follows an expr, a bit_op, or an add_op
+        let mut term = follow("expr");
+        term.extend(first("bit_op"));
+        term.extend(first("add_op"));
+        assert_eq!(follow("term"), term);
+        assert!(follow("expr").contains(&TokTp::To));
+    }
+
+    #[test]
+    #[should_panic(expected = "no nonterminal named stmts")]
+    fn unknown_nonterminal() {
+        first("stmts");
+    }
+}
@@ -0,0 +1,471 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Input buffering
+//
+//  Provides the scanner with characters of stdin or a file, one at a
+//  time, tagged with source line and column.  Several files can be read
+//  in turn as one stream; line numbers then run on from one file to the
+//  next, and file_at() says which file a line came from.
+//
+//  Columns are display columns, as an editor would show them: each
+//  character takes one, except that a tab advances to the next tab stop
+//  (every 8 columns by default).  They are not byte or codepoint indices.
+//
+//  Lines may end in LF, CR LF, or a lone CR (as on the classic Mac OS);
+//  getc() returns a single NL for each.
+//
+//  Does not assume input is ASCII, but iterates over Unicode codepoints,
+//  not graphemes, so diacritics are returned as separate characters.
+//
+//  A backslash at the very end of a line joins it to the next: neither
+//  the backslash nor the newline is returned.  As in C, this happens
+//  before the scanner sees anything, so it works inside comments and
+//  literals too.  Characters keep their physical line and column.
+//
+
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::fs::File;
+use std::mem;
+
+pub struct SourceChar {
+    pub ch: char,
+    pub line: usize,    // 1-based
+    pub col: usize,     // 0-based display column
+}
+
+pub const EOF: char = '\x04';   // ^D sentinel
+const NL:  char = '\x0a';   // ^J
+const CR:  char = '\x0d';   // ^M
+
+// Strangely, Rust's standard str and String types don't provide an easy
+// and efficient way to inspect their last character.  This adds one.
+trait StringEnd {
+    fn last_char(&self) -> Option<char>;
+}
+impl StringEnd for str {
+    // Return last character of string, if there is one.  Takes O(1) time:
+    // decoding UTF-8 from the back looks at no more than 4 bytes.
+    fn last_char (self: &str) -> Option<char> {
+        self.chars().next_back()
+    }
+}
+
+// All sources share the line buffering below; only where lines come
+// from differs.
+pub struct Input {
+    reader: Box<dyn BufRead>,
+    pending: Vec<(String, Box<dyn BufRead>)>,   // files still to read, last first
+    files: Vec<(String, usize)>,    // each file read so far, and its first line
+    buf: String,
+    rest: String,       // what followed a lone CR in the last line read
+    line: usize,
+    next_byte: usize,   // index in buf of next unread character
+    next_col: usize,    // display column of next unread character
+    tab_width: usize,
+    at_eof: bool,       // reader is exhausted
+    lines: Option<Vec<String>>,     // lines since set_keep_lines(), if called
+    first_kept: usize,  // number of lines[0]
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::from_reader(io::stdin().lock())
+    }
+
+    pub fn from_file(path: &str) -> io::Result<Self> {
+        Self::from_files(&[path])
+    }
+
+    // Read the named files one after another.  They are all opened up
+    // front, so a missing one is reported (with its name) before any
+    // input is read.
+    pub fn from_files<S: AsRef<str>>(paths: &[S]) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let file = File::open(path).map_err(|e|
+                io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+            files.push((path.to_string(), Box::new(BufReader::new(file)) as Box<dyn BufRead>));
+        }
+        files.reverse();
+        let (first, reader) = files.pop().unwrap_or_else(||
+            (String::new(), Box::new(io::empty())));
+        let mut input = Self::from_reader(reader);
+        input.pending = files;
+        if !paths.is_empty() {
+            input.files.push((first, 1));
+        }
+        Ok(input)
+    }
+
+    // Read from any buffered source, e.g. a socket or an in-memory
+    // &[u8].  new() and from_file() are thin wrappers around this.
+    pub fn from_reader(reader: impl BufRead + 'static) -> Self {
+        Self {
+            reader: Box::new(reader),
+            pending: Vec::new(),
+            files: Vec::new(),
+            buf: String::new(),     // empty zero-th line
+            rest: String::new(),
+            line: 0,
+            next_byte: 0,
+            next_col: 0,
+            tab_width: 8,
+            at_eof: false,
+            lines: None,
+            first_kept: 0,
+        }
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    // Set the distance between tab stops (default 8); must be positive.
+    pub fn set_tab_width(&mut self, width: usize) {
+        assert!(width > 0, "tab width must be positive");
+        self.tab_width = width;
+    }
+
+    // Keep the text of every line read from now on, for line_text().
+    // Off by default, since it holds the whole input in memory.
+    pub fn set_keep_lines(&mut self, keep: bool) {
+        self.lines = if keep { Some(Vec::new()) } else { None };
+        self.first_kept = self.line + 1;
+    }
+
+    // Serve characters from an in-memory string, line by line, exactly
+    // as if it had arrived on stdin.  Handy for tests and embedding.
+    pub fn from_str(src: &str) -> Self {
+        Self::from_reader(io::Cursor::new(src.to_owned().into_bytes()))
+    }
+
+    // getc() is a lot like Iterator::next(), but it doesn't return an Option.
+    // Instead, it returns a sentinel (EOF) at end of file.  This relieves the
+    // scanner of the need to call next().unwrap_or(SourceChar{ EOF, _, _ })
+    pub fn getc(&mut self) -> SourceChar {
+        loop {
+            let col = self.next_col;    // column of char we will be returning
+
+            // use iterator once to get the next UTF8 char
+            if let Some(ch) = self.buf[self.next_byte..].chars().next() {
+                self.next_byte += ch.len_utf8();
+                // only the last character of buf is a NL
+                if ch == '\\' && self.buf[self.next_byte..].starts_with(NL) {
+                    self.next_byte = self.buf.len();    // continued line
+                    continue;
+                }
+                self.next_col = if ch == '\t' {
+                    (col / self.tab_width + 1) * self.tab_width
+                } else {
+                    col + 1
+                };
+                return SourceChar { ch, line: self.line, col };
+            }
+            // EOF belongs to the (empty) line after the last one
+            if self.at_eof {
+                return SourceChar { ch: EOF, line: self.line, col };
+            }
+            self.at_eof = !self.refill();
+            self.line += 1;
+            self.next_byte = 0;
+            self.next_col = 0;
+        }
+    }
+
+    // Replace buf with the next line, ending it in exactly one NL whatever
+    // the source used (LF, CR LF, CR, or nothing at the end of the last
+    // line).  Returns false, leaving buf empty, if there are no more lines.
+    // The buffer's capacity is reused from line to line.
+    fn refill(&mut self) -> bool {
+        self.buf.clear();
+        // read_line() only splits at LF, so what it returns may hold
+        // several CR-terminated lines; the ones after the first wait in
+        // rest
+        if !self.rest.is_empty() {
+            mem::swap(&mut self.buf, &mut self.rest);
+        } else {
+            loop {
+                let count = self.reader.read_line(&mut self.buf)
+                    .expect("Can't read input!");
+                if count > 0 {
+                    break;
+                }
+                match self.pending.pop() {
+                    Some((name, reader)) => {
+                        self.reader = reader;
+                        self.files.push((name, self.line + 1));
+                    }
+                    None => return false,
+                }
+            }
+        }
+        if self.buf.last_char() == Some(NL) {
+            self.buf.pop();
+        }
+        if let Some(i) = self.buf.find(CR) {
+            if i + 1 < self.buf.len() {
+                self.rest = self.buf.split_off(i + 1);
+            }
+        }
+        if self.buf.last_char() == Some(CR) {
+            self.buf.pop();
+        }
+        if let Some(ref mut lines) = self.lines {
+            lines.push(self.buf.clone());
+        }
+        self.buf.push(NL);
+        true
+    }
+
+    // Return the character after the one most recently returned by
+    // getc() (i.e. after the scanner's peeked next_char) without
+    // consuming it; EOF at end of file.  Input is buffered one line at
+    // a time, so peeking past a newline reads the next line in: the
+    // result is that line's first character, and the current line is
+    // no longer available.
+    pub fn peek2(&mut self) -> char {
+        let sc = self.getc();
+        // un-read it (getc doesn't move past EOF)
+        if sc.ch != EOF {
+            self.next_byte -= sc.ch.len_utf8();
+        }
+        self.next_col = sc.col;
+        sc.ch
+    }
+
+    // The number and text (without its newline) of the line most recently
+    // read in.  After end of input that is the empty line after the last.
+    pub fn current_line(&self) -> (usize, &str) {
+        (self.line, self.buf.strip_suffix(NL).unwrap_or(&self.buf))
+    }
+
+    // The text (without its newline) of line n: the current line, or with
+    // set_keep_lines() any line read since.  None for a line not yet read
+    // or not kept.
+    pub fn line_text(&self, n: usize) -> Option<&str> {
+        // buf is empty only before the first line and after the last
+        if n == self.line && !self.buf.is_empty() {
+            return Some(self.current_line().1);
+        }
+        match self.lines {
+            Some(ref lines) if n >= self.first_kept => {
+                lines.get(n - self.first_kept).map(String::as_str)
+            }
+            _ => None,
+        }
+    }
+
+    // The name of the file that the given line was read from, or None if
+    // input isn't from files.
+    pub fn file_at(&self, line: usize) -> Option<&str> {
+        self.files.iter().rev()
+            .find(|&&(_, first)| first <= line)
+            .map(|(name, _)| name.as_str())
+    }
+
+} // end impl Input
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_appends_newline_then_eof() {
+        let mut input = Input::from_reader(&b"ab"[..]);
+        let got: Vec<(char, usize, usize)> = (0..4)
+            .map(|_| { let sc = input.getc(); (sc.ch, sc.line, sc.col) })
+            .collect();
+        assert_eq!(got, vec![('a', 1, 0), ('b', 1, 1), (NL, 1, 2), (EOF, 2, 0)]);
+        assert_eq!(input.getc().ch, EOF);   // EOF is sticky
+    }
+
+    #[test]
+    fn last_char() {
+        assert_eq!("abc".last_char(), Some('c'));
+        assert_eq!("café".last_char(), Some('é'));
+        assert_eq!("x\n".last_char(), Some(NL));
+        assert_eq!("é".last_char(), Some('é'));
+        assert_eq!("".last_char(), None);
+    }
+
+    #[test]
+    fn one_newline_per_line() {
+        let got: Vec<char> = drain(Input::from_str("a\nb\n")).into_iter()
+            .map(|(ch, _, _)| ch).collect();
+        assert_eq!(got, vec!['a', NL, 'b', NL, EOF]);
+        let got = drain(Input::from_str("é\nü"));
+        assert_eq!(got, vec![('é', 1, 0), (NL, 1, 1), ('ü', 2, 0), (NL, 2, 1),
+            (EOF, 3, 0)]);
+    }
+
+    fn drain(mut input: Input) -> Vec<(char, usize, usize)> {
+        let mut out = Vec::new();
+        loop {
+            let sc = input.getc();
+            out.push((sc.ch, sc.line, sc.col));
+            if sc.ch == EOF { return out; }
+        }
+    }
+
+    #[test]
+    fn from_str_matches_reader() {
+        let src = "int x := 1\n  write x";
+        assert_eq!(drain(Input::from_str(src)),
+                   drain(Input::from_reader(io::Cursor::new(src.as_bytes().to_vec()))));
+        let got = drain(Input::from_str("é+1"));
+        assert_eq!(got, vec![('é', 1, 0), ('+', 1, 1), ('1', 1, 2), (NL, 1, 3),
+            (EOF, 2, 0)]);
+        assert_eq!(drain(Input::from_str("")), vec![(EOF, 1, 0)]);
+    }
+
+    #[test]
+    fn peek2_does_not_consume() {
+        let mut input = Input::from_str("ab");
+        assert_eq!(input.peek2(), 'a');     // reads line 1 in
+        assert_eq!(input.getc().ch, 'a');
+        assert_eq!(input.peek2(), 'b');
+        assert_eq!(input.peek2(), 'b');
+        let sc = input.getc();
+        assert_eq!((sc.ch, sc.line, sc.col), ('b', 1, 1));
+        assert_eq!(input.getc().ch, NL);
+        assert_eq!(input.peek2(), EOF);
+        assert_eq!(input.getc().ch, EOF);
+
+        // peeking at a tab doesn't move the column
+        let mut input = Input::from_str("\t\tx");
+        input.getc();
+        assert_eq!(input.peek2(), '\t');
+        let sc = input.getc();
+        assert_eq!((sc.ch, sc.col), ('\t', 8));
+        assert_eq!(input.peek2(), 'x');
+        assert_eq!(input.getc().col, 16);
+    }
+
+    #[test]
+    fn tabs_advance_to_tab_stops() {
+        assert_eq!(drain(Input::from_str("\tx\ty\t\tz")),
+            vec![('\t', 1, 0), ('x', 1, 8), ('\t', 1, 9), ('y', 1, 16), ('\t', 1, 17),
+                 ('\t', 1, 24), ('z', 1, 32), (NL, 1, 33), (EOF, 2, 0)]);
+        let mut input = Input::from_str("ab\tc");
+        input.set_tab_width(4);
+        let cols: Vec<usize> = drain(input).into_iter().map(|(_, _, col)| col).collect();
+        assert_eq!(cols, vec![0, 1, 2, 4, 5, 0]);
+    }
+
+    #[test]
+    fn crlf_becomes_nl() {
+        assert_eq!(drain(Input::from_str("a\r\nbc\r\n")),
+            vec![('a', 1, 0), (NL, 1, 1), ('b', 2, 0), ('c', 2, 1), (NL, 2, 2),
+                 (EOF, 3, 0)]);
+        assert_eq!(drain(Input::from_str("a\r")),
+            vec![('a', 1, 0), (NL, 1, 1), (EOF, 2, 0)]);
+    }
+
+    #[test]
+    fn lone_cr_ends_a_line() {
+        let got = drain(Input::from_str("a := 1\rb := 2\r"));
+        let lines: Vec<(char, usize, usize)> = got.into_iter()
+            .filter(|&(ch, _, _)| ch == 'a' || ch == 'b' || ch == '2' || ch == NL || ch == EOF)
+            .collect();
+        assert_eq!(lines, vec![('a', 1, 0), (NL, 1, 6), ('b', 2, 0), ('2', 2, 5), (NL, 2, 6),
+            (EOF, 3, 0)]);
+        // mixed with the other endings, and blank lines, and no ending at all
+        assert_eq!(drain(Input::from_str("a\r\rb\r\nc\nd")),
+            vec![('a', 1, 0), (NL, 1, 1), (NL, 2, 0), ('b', 3, 0), (NL, 3, 1),
+                 ('c', 4, 0), (NL, 4, 1), ('d', 5, 0), (NL, 5, 1), (EOF, 6, 0)]);
+        // each is a line of its own to current_line() and line_text()
+        let mut input = Input::from_str("x\ryz\r");
+        input.set_keep_lines(true);
+        while input.getc().ch != 'y' {}
+        assert_eq!(input.current_line(), (2, "yz"));
+        assert_eq!(input.line_text(1), Some("x"));
+    }
+
+    #[test]
+    fn backslash_continues_lines() {
+        assert_eq!(drain(Input::from_str("a\\\nb\\c\n")),
+            vec![('a', 1, 0), ('b', 2, 0), ('\\', 2, 1), ('c', 2, 2), (NL, 2, 3),
+                 (EOF, 3, 0)]);
+        // also with CR LF, several times over, and at end of file
+        assert_eq!(drain(Input::from_str("a\\\r\n\\\nb\\")),
+            vec![('a', 1, 0), ('b', 3, 0), (EOF, 4, 0)]);
+        let mut input = Input::from_str("/\\\n/");
+        assert_eq!(input.getc().ch, '/');
+        assert_eq!(input.peek2(), '/');
+        assert_eq!(drain(input), vec![('/', 2, 0), (NL, 2, 1), (EOF, 3, 0)]);
+    }
+
+    #[test]
+    fn last_line_without_newline() {
+        assert_eq!(drain(Input::from_str("42")),
+            vec![('4', 1, 0), ('2', 1, 1), (NL, 1, 2), (EOF, 2, 0)]);
+        assert_eq!(drain(Input::from_str("")), vec![(EOF, 1, 0)]);
+        // peeking at the synthetic newline doesn't disturb what follows
+        let mut input = Input::from_str("/");
+        assert_eq!(input.getc().ch, '/');
+        assert_eq!(input.peek2(), NL);
+        assert_eq!(drain(input), vec![(NL, 1, 1), (EOF, 2, 0)]);
+    }
+
+    #[test]
+    fn files_are_read_one_after_another() {
+        let dir = std::env::temp_dir();
+        let a = dir.join(format!("calc-input-{}-a", std::process::id()));
+        let b = dir.join(format!("calc-input-{}-b", std::process::id()));
+        std::fs::write(&a, "x\ny").unwrap();
+        std::fs::write(&b, "z\n").unwrap();
+        let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+        let mut input = Input::from_files(&[a, b]).unwrap();
+        let got: Vec<char> = (0..6).map(|_| input.getc().ch).collect();
+        assert_eq!(got, vec!['x', NL, 'y', NL, 'z', NL]);
+        assert_eq!(input.getc().line, 4);
+        assert_eq!(input.file_at(2), Some(a));
+        assert_eq!(input.file_at(3), Some(b));
+        assert_eq!(Input::from_str("x").file_at(1), None);
+
+        let missing = dir.join(format!("calc-input-{}-missing", std::process::id()));
+        let missing = missing.to_str().unwrap();
+        let err = Input::from_files(&[a, missing]).err().unwrap();
+        assert!(err.to_string().starts_with(&format!("{}: ", missing)));
+        std::fs::remove_file(a).unwrap();
+        std::fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn kept_lines() {
+        let src = "one\ntwo\r\nthree \\\nfour";
+        let mut input = Input::from_str(src);
+        input.getc();
+        assert_eq!(input.line_text(1), Some("one"));
+        assert_eq!(input.line_text(2), None);   // not read yet
+        drain(input);
+
+        let mut input = Input::from_str(src);
+        input.set_keep_lines(true);
+        assert_eq!(input.line_text(0), None);
+        let mut sc = input.getc();
+        while sc.line < 4 {
+            sc = input.getc();
+        }
+        assert_eq!((1..=5).map(|n| input.line_text(n)).collect::<Vec<_>>(),
+            vec![Some("one"), Some("two"), Some("three \\"), Some("four"), None]);
+        drain(input);
+
+        // only lines read after keeping starts are kept
+        let mut input = Input::from_str(src);
+        input.getc();
+        input.set_keep_lines(true);
+        while input.getc().ch != EOF {}
+        assert_eq!(input.line_text(1), None);
+        assert_eq!(input.line_text(2), Some("two"));
+        assert_eq!(input.line_text(4), Some("four"));
+    }
+}
@@ -0,0 +1,75 @@
+/*****************************************************************************
+    Complete recursive descent parser for the calculator language.
+    Builds on figure 2.16 in the text.  Prints a trace of productions
+    predicted and tokens matched.  On invalid input, reports a syntax
+    error and resumes at the next statement, so later errors are
+    reported too.
+
+    (c) Michael L. Scott, 2023
+    For use by students in CSC 2/454 at the University of Rochester,
+    during the Fall 2023 term.  All other use requires written
+    permission of the author.
+
+    The bulk of the provided code consists of methods for three structs,
+    which function much like classes in an OO language:
+    Input (input.rs)
+        buffers stdin, a file, or a string a line at a time and
+        provides the scanner w/ characters
+    Scanner (scanner.rs)
+        peeks ahead one character and provides the parser w/ tokens
+    Parser (parser.rs)
+        peeks ahead one token, checks syntax of calculator program,
+        and builds an abstract syntax tree (ast.rs) and, optionally, a
+        parse tree (parse_tree.rs)
+    grammar.rs
+        the productions the parser predicts, and their FIRST and
+        FOLLOW sets
+    visit.rs
+        Visitor, for passes over the syntax tree
+    semantic.rs
+        checks that variables are declared before use, and that int
+        and real values aren't mixed
+    eval.rs
+        executes the syntax tree
+    pretty.rs
+        turns the syntax tree back into canonically formatted source
+    fold.rs
+        folds constant subexpressions in the syntax tree
+    span.rs
+        Span, the stretch of source a token or syntax tree node came from
+    error.rs
+        CalcError, the lexical, syntax, and semantic diagnostics
+
+    This file makes them a library, crate `calc`; main.rs is the
+    command-line driver.  Build with
+        rustc --crate-type=lib --crate-name=calc lib.rs
+        rustc main.rs --extern calc=libcalc.rlib
+    The optional serde feature (--cfg 'feature="serde"', plus --extern
+    for serde and, in main.rs, serde_json) adds --ast-json.  The
+    optional unicode-normalization feature (with --extern for the crate
+    of that name) normalizes identifiers to NFC.
+ *****************************************************************************/
+
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "unicode-normalization")]
+extern crate unicode_normalization;
+
+pub mod span;
+pub mod error;
+pub mod input;
+pub mod scanner;
+pub mod ast;
+pub mod parser;
+pub mod grammar;
+pub mod parse_tree;
+pub mod visit;
+pub mod semantic;
+pub mod eval;
+pub mod pretty;
+pub mod fold;
+
+pub use error::CalcError;
+pub use parser::Parser;
+pub use scanner::{Scanner, ScannerConfig, TokTp, Token};
+pub use span::Span;
@@ -0,0 +1,483 @@
+/*****************************************************************************
+    Command-line driver for the calculator language; the scanner,
+    parser, and interpreter live in the calc library (lib.rs).
+ *****************************************************************************/
+
+extern crate calc;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use calc::ast::Stmt;
+use calc::eval;
+use calc::fold::fold_program;
+use calc::input::Input;
+use calc::pretty::pretty_print;
+use calc::semantic::{check, shadowed_variables, unassigned_reads, unused_variables};
+use calc::{CalcError, Parser, Scanner, TokTp};
+use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::io::Write;
+use std::process;
+
+// Usage: parser [--quiet] [--trace-pos] [--max-errors N] [--prompt STR]
+//                [--run | --check | --tokens | --dump-tokens | --stats | --parse-stats
+//                 | --ast-json | --format | --fold | --dot]
+//                [file... | -e program]
+//        parser --repl
+// The program is read from the named files, one after another, or from
+// stdin if there are none.  -e (or --expr) gives the program text itself
+// as the next argument instead, e.g. parser --run -e 'write 2 + 3'.
+// Line numbers run on from file to file, and with more than one file
+// each diagnostic starts with its file's name.
+// It is always parsed, printing the trace unless --quiet (with
+// --trace-pos, each line of it starts with the [line:col] of the token
+// it's at), and checked for undeclared variables and type errors, with a
+// warning for each variable that is never used or is read before it's
+// assigned; with --run it is then executed.  With --check, nothing is
+// printed but the errors, and the exit status says whether there were
+// any.
+// With --tokens it is only scanned, and each token printed as a line of
+// JSON; --dump-tokens prints them in aligned columns instead, End
+// included, and --stats just counts them by kind.  With --ast-json
+// (serde feature only) the syntax tree is printed as JSON once parsing
+// succeeds, and with --format it is printed as source text in canonical
+// layout; --fold does the same after folding constant subexpressions.
+// With --dot, the parse tree is printed in Graphviz DOT form (render
+// with dot -Tpng), and with --parse-stats just the number of productions
+// predicted and tokens matched, and how deeply productions nest.  These
+// last five imply --quiet.
+// Since read statements take their input from stdin, a program that reads
+// should be given as a file.  When stdin is a terminal, each value read
+// is prompted for with "? ", or with STR given --prompt STR.
+// Parsing stops after N syntax and lexical errors (20 by default; 0 for
+// no limit) with --max-errors N.
+// With --repl, statements are read from stdin a line at a time and run
+// as they are entered; see repl() below.
+fn main() {
+    let mut run = false;
+    let mut check_only = false;
+    let mut tokens = false;
+    let mut dump = false;
+    let mut stats = false;
+    let mut ast_json = false;
+    let mut format = false;
+    let mut fold = false;
+    let mut dot = false;
+    let mut parse_stats = false;
+    let mut quiet = false;
+    let mut trace_pos = false;
+    let mut max_errors = None;
+    let mut prompt = eval::DEFAULT_PROMPT.to_string();
+    let mut repl_mode = false;
+    let mut paths = Vec::new();
+    let mut expr = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--run" {
+            run = true;
+        } else if arg == "--check" {
+            check_only = true;
+        } else if arg == "--tokens" {
+            tokens = true;
+        } else if arg == "--dump-tokens" {
+            dump = true;
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--ast-json" {
+            ast_json = true;
+        } else if arg == "--format" {
+            format = true;
+        } else if arg == "--fold" {
+            fold = true;
+        } else if arg == "--dot" {
+            dot = true;
+        } else if arg == "--parse-stats" {
+            parse_stats = true;
+        } else if arg == "--quiet" {
+            quiet = true;
+        } else if arg == "--trace-pos" {
+            trace_pos = true;
+        } else if arg == "--max-errors" {
+            match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => max_errors = Some(n),
+                None => {
+                    eprintln!("--max-errors needs a number of errors");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--prompt" {
+            match args.next() {
+                Some(text) => prompt = text,
+                None => {
+                    eprintln!("--prompt needs the text to prompt with");
+                    process::exit(1);
+                }
+            }
+        } else if arg == "--repl" {
+            repl_mode = true;
+        } else if arg == "-e" || arg == "--expr" {
+            match args.next() {
+                Some(src) => expr = Some(src),
+                None => {
+                    eprintln!("{} needs a program to run", arg);
+                    process::exit(1);
+                }
+            }
+        } else {
+            paths.push(arg);
+        }
+    }
+    if repl_mode {
+        repl(&prompt);
+        return;
+    }
+    if expr.is_some() && !paths.is_empty() {
+        eprintln!("-e and input files are mutually exclusive: give one or the other");
+        process::exit(1);
+    }
+    let input = if let Some(src) = expr {
+        Input::from_str(&src)
+    } else if paths.is_empty() {
+        Input::new()
+    } else {
+        Input::from_files(&paths).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        })
+    };
+    if tokens {
+        print_tokens(Scanner::from_input(input));
+        return;
+    }
+    if dump {
+        dump_tokens(Scanner::from_input(input));
+        return;
+    }
+    if stats {
+        print_stats(Scanner::from_input(input));
+        return;
+    }
+    let mut parser = Parser::from_scanner(Scanner::from_input(input));
+    if dot {
+        parser.build_parse_tree();
+    }
+    if let Some(n) = max_errors {
+        parser.set_max_errors(n);
+    }
+    if check_only {
+        parser.set_trace(false);
+        let ok = check_program(&mut parser, paths.len(), &mut io::stderr());
+        process::exit(if ok { 0 } else { 1 });
+    }
+    // the trace would get in the way of the other outputs
+    parser.set_trace(!(quiet || dot || ast_json || format || fold || parse_stats));
+    parser.set_trace_pos(trace_pos);
+    let stmts = match parser.parse() {
+        Ok(stmts) => stmts,
+        Err(_) => {
+            print_syntax_errors(&parser, paths.len(), &mut io::stderr());
+            process::exit(1);
+        }
+    };
+    if parse_stats {
+        let stats = parser.stats();
+        for (what, count) in &[("productions predicted", stats.productions_predicted),
+                ("tokens matched", stats.tokens_matched), ("maximum depth", stats.max_depth)] {
+            println!("{:<22}{:>6}", what, count);
+        }
+        return;
+    }
+    if let Some(tree) = parser.parse_tree() {
+        print!("{}", tree.to_dot());
+        return;
+    }
+    if ast_json {
+        print_ast_json(&stmts);
+        return;
+    }
+    if format {
+        print!("{}", pretty_print(&stmts));
+        return;
+    }
+    if fold {
+        print!("{}", pretty_print(&fold_program(stmts)));
+        return;
+    }
+    let errors = check(&stmts);
+    if !errors.is_empty() {
+        print_semantic_errors(&parser, paths.len(), &errors, &mut io::stderr());
+        process::exit(1);
+    }
+    let mut warnings = unused_variables(&stmts);
+    warnings.extend(shadowed_variables(&stmts));
+    warnings.extend(unassigned_reads(&stmts));
+    warnings.sort_by_key(|w| w.line);
+    for w in warnings {
+        eprintln!("{}{}", file_prefix(&parser, paths.len(), w.line), w);
+    }
+    if run {
+        if let Err(e) = eval::run_with_prompt(&stmts, &mut eval::Env::new(), &prompt) {
+            eprintln!("{}{}", file_prefix(&parser, paths.len(), e.line), e);
+            process::exit(1);
+        }
+    }
+}
+
+// Parse and check the program, writing any errors to err but nothing
+// else; true if there were none.
+fn check_program(parser: &mut Parser, files: usize, err: &mut dyn Write) -> bool {
+    let stmts = match parser.parse() {
+        Ok(stmts) => stmts,
+        Err(_) => {
+            print_syntax_errors(parser, files, err);
+            return false;
+        }
+    };
+    let errors = check(&stmts);
+    if !errors.is_empty() {
+        print_semantic_errors(parser, files, &errors, err);
+        return false;
+    }
+    true
+}
+
+// The parser's lexical and syntax errors, and how many there were.
+fn print_syntax_errors(parser: &Parser, files: usize, err: &mut dyn Write) {
+    let errors = parser.errors();
+    for e in errors {
+        writeln!(err, "{}{}", file_prefix(parser, files, e.line()), parser.diagnostic(e))
+            .expect("can't write errors");
+    }
+    if parser.gave_up() {
+        writeln!(err, "too many errors; stopping").expect("can't write errors");
+    }
+    writeln!(err, "{} error{}", errors.len(), if errors.len() == 1 { "" } else { "s" })
+        .expect("can't write errors");
+}
+
+fn print_semantic_errors(parser: &Parser, files: usize, errors: &[CalcError],
+        err: &mut dyn Write) {
+    for e in errors {
+        writeln!(err, "{}semantic error: {}", file_prefix(parser, files, e.line()), e)
+            .expect("can't write errors");
+    }
+    writeln!(err, "{} semantic error{}", errors.len(), if errors.len() == 1 { "" } else { "s" })
+        .expect("can't write errors");
+}
+
+// "name: " for the file that line came from, if there are several files
+// to choose from; otherwise nothing.
+fn file_prefix(parser: &Parser, files: usize, line: usize) -> String {
+    match parser.scanner().input().file_at(line) {
+        Some(name) if files > 1 => format!("{}: ", name),
+        _ => String::new(),
+    }
+}
+
+// Read-eval-print loop.  Each line is parsed and run on its own against
+// one environment, so variables outlive the line that set them.  Errors
+// are printed and the loop carries on; it ends at EOF.  Lines aren't
+// semantically checked, since the checker only sees one line at a time;
+// undefined variables are caught when the line runs instead.  A read
+// takes the next line of stdin as its value, after prompt.
+fn repl(prompt: &str) {
+    let mut env = eval::Env::new();
+    loop {
+        print!("> ");
+        io::stdout().flush().expect("can't flush stdout");
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => {
+                println!();
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+        let mut parser = Parser::from_scanner(Scanner::from_input(Input::from_str(&line)));
+        parser.set_trace(false);
+        let stmts = match parser.parse() {
+            Ok(stmts) => stmts,
+            Err(_) => {
+                for e in parser.errors() {
+                    eprintln!("{}", e);
+                }
+                continue;
+            }
+        };
+        if let Err(e) = eval::run_with_prompt(&stmts, &mut env, prompt) {
+            eprintln!("{}", e);
+            continue;
+        }
+        echo_assignments(&stmts, &env, &mut io::stdout());
+    }
+}
+
+// Echo the value of each variable the line assigned.  A failed check
+// ends the line early, so a variable it would have set may have no value
+// yet, and isn't shown.
+fn echo_assignments(stmts: &[Stmt], env: &eval::Env, out: &mut dyn Write) {
+    for stmt in stmts {
+        match *stmt {
+            Stmt::Assign { ref name, .. } | Stmt::Decl { ref name, .. }
+            | Stmt::Const { ref name, .. } => {
+                if let Some(v) = env.get(name) {
+                    writeln!(out, "{} = {}", name, v).expect("can't write output");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Print each token as JSON, one per line; lexical errors go to stderr.
+fn print_tokens(mut scanner: Scanner) {
+    let mut failed = false;
+    loop {
+        match scanner.scan() {
+            Ok(ref tok) if tok.tp == TokTp::End => break,
+            Ok(tok) => println!("{}", tok.to_json()),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// Print each token, End included, as "line:col  type  'text'" in
+// columns; lexical errors go to stderr as they're found.
+fn dump_tokens(mut scanner: Scanner) {
+    let mut failed = false;
+    loop {
+        match scanner.scan() {
+            Ok(tok) => {
+                let pos = format!("{}:{}", tok.span.start_line, tok.span.start_col);
+                println!("{:<8}{:<11}'{}'", pos, tok.tp, tok.text.escape_debug());
+                if tok.tp == TokTp::End {
+                    break;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// Count the tokens of each kind, and the distinct identifiers; lexical
+// errors go to stderr.
+fn print_stats(mut scanner: Scanner) {
+    let mut failed = false;
+    let (mut total, mut idents, mut literals, mut keywords, mut operators, mut punctuation)
+        = (0, 0, 0, 0, 0, 0);
+    let mut names = HashSet::new();
+    loop {
+        let tok = match scanner.scan() {
+            Ok(ref tok) if tok.tp == TokTp::End => break,
+            Ok(tok) => tok,
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+        total += 1;
+        match tok.tp {
+            TokTp::Ident => {
+                idents += 1;
+                names.insert(tok.text);
+            }
+            TokTp::ILit | TokTp::RLit | TokTp::SLit | TokTp::CLit => literals += 1,
+            TokTp::Read | TokTp::Write | TokTp::If | TokTp::Elsif | TokTp::Else | TokTp::Fi
+            | TokTp::Do | TokTp::Od | TokTp::Check | TokTp::Assert | TokTp::For | TokTp::To | TokTp::Const
+            | TokTp::Int | TokTp::Real | TokTp::Trunc | TokTp::Float => keywords += 1,
+            TokTp::LParen | TokTp::RParen | TokTp::LBrace | TokTp::RBrace | TokTp::Comma
+            | TokTp::Semi => punctuation += 1,
+            _ => operators += 1,
+        }
+    }
+    for (what, count) in &[("tokens", total), ("identifiers", idents),
+            ("distinct identifiers", names.len()), ("literals", literals),
+            ("keywords", keywords), ("operators", operators), ("punctuation", punctuation)] {
+        println!("{:<22}{:>6}", what, count);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+#[cfg(feature = "serde")]
+fn print_ast_json(stmts: &[Stmt]) {
+    println!("{}", serde_json::to_string_pretty(stmts).expect("AST is serializable"));
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_ast_json(_stmts: &[Stmt]) {
+    eprintln!("--ast-json needs the serde feature");
+    process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A parser for src with tracing off.  The library's test helpers
+    // aren't visible from here.
+    fn parser(src: &str) -> Parser {
+        let mut parser = Parser::from_scanner(Scanner::from_str(src));
+        parser.set_trace(false);
+        parser
+    }
+
+    // What --check prints for src, and whether it passes.
+    fn check_src(src: &str) -> (bool, String) {
+        let mut parser = parser(src);
+        let mut err = Vec::new();
+        let ok = check_program(&mut parser, 1, &mut err);
+        (ok, String::from_utf8(err).unwrap())
+    }
+
+    #[test]
+    fn check_mode() {
+        assert_eq!(check_src("int x := 2\nwrite x * 3"), (true, String::new()));
+        // an unused variable is only a warning
+        assert_eq!(check_src("int x := 2"), (true, String::new()));
+        let (ok, err) = check_src("x := * 3");
+        assert!(!ok);
+        assert!(err.starts_with("syntax error on line 1, col 5"), "{}", err);
+        assert!(err.ends_with("\n1 error\n"), "{}", err);
+        let (ok, err) = check_src("int x := 2\nwrite y\nwrite z");
+        assert!(!ok);
+        assert_eq!(err.lines().last(), Some("2 semantic errors"));
+    }
+
+    // What the REPL prints for one line, after running it in env.
+    fn repl_line(src: &str, env: &mut eval::Env) -> String {
+        let stmts = parser(src).parse().unwrap();
+        eval::run_with_io(&stmts, env, &mut io::empty(), &mut io::sink()).unwrap();
+        let mut out = Vec::new();
+        echo_assignments(&stmts, env, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn repl_echoes_what_was_set() {
+        let mut env = eval::Env::new();
+        assert_eq!(repl_line("x := 2 int y := x + 1", &mut env), "x = 2\ny = 3\n");
+        // a failed check at the top level ends the line before x := 5
+        assert_eq!(repl_line("check 1 > 2 z := 5", &mut env), "");
+        assert_eq!(env.get("z"), None);
+    }
+}
@@ -0,0 +1,137 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Parse trees
+//
+//  Built from the parser's trace: each prediction adds a node for its
+//  nonterminal, and each matched token a leaf.  Since the parser derives
+//  leftmost, every new node is the next child of the innermost node whose
+//  right-hand side isn't yet complete.  The tree can be written out in
+//  Graphviz DOT form.
+//
+
+struct Node {
+    label: String,
+    leaf: bool,
+    parent: Option<usize>,
+}
+
+pub struct ParseTree {
+    nodes: Vec<Node>,
+    open: Vec<(usize, usize)>,  // incomplete nodes and their missing children
+}
+
+impl Default for ParseTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParseTree {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new(), open: Vec::new() }
+    }
+
+    // Record the expansion of nonterminal name into rhs_len symbols.
+    pub fn nonterminal(&mut self, name: &str, rhs_len: usize) {
+        let node = self.add(name, false);
+        self.open.push((node, rhs_len));
+        self.close_complete();
+    }
+
+    // Record a matched token.
+    pub fn terminal(&mut self, text: &str) {
+        self.add(text, true);
+        self.close_complete();
+    }
+
+    fn add(&mut self, label: &str, leaf: bool) -> usize {
+        let parent = self.open.last_mut().map(|&mut (node, ref mut missing)| {
+            *missing -= 1;
+            node
+        });
+        self.nodes.push(Node { label: label.to_string(), leaf, parent });
+        self.nodes.len() - 1
+    }
+
+    fn close_complete(&mut self) {
+        while let Some(&(_, 0)) = self.open.last() {
+            self.open.pop();
+        }
+    }
+
+    // Nonterminals are ellipses and tokens boxes; children appear left to
+    // right in the order they were derived.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n    ordering=out;\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            out.push_str(&format!("    n{} [label={}{}];\n", i, dot_string(&node.label),
+                if node.leaf { ", shape=box" } else { "" }));
+            if let Some(parent) = node.parent {
+                out.push_str(&format!("    n{} -> n{};\n", parent, i));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn dot_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parser;
+
+    #[test]
+    fn tree_from_parse() {
+        let mut parser = parser("x := 7");
+        parser.build_parse_tree();
+        parser.parse().unwrap();
+        let dot = parser.parse_tree().unwrap().to_dot();
+        let expected = [
+            "n0 [label=\"program\"];",
+            "n1 [label=\"stmt_list\"];", "n0 -> n1;",
+            "n2 [label=\"stmt\"];", "n1 -> n2;",
+            "n3 [label=\"x\", shape=box];", "n2 -> n3;",
+            "n4 [label=\":=\", shape=box];", "n2 -> n4;",
+            "n5 [label=\"assign_value\"];", "n2 -> n5;",
+            "n6 [label=\"expr\"];", "n5 -> n6;",
+            "n7 [label=\"sum\"];", "n6 -> n7;",
+            "n8 [label=\"term\"];", "n7 -> n8;",
+            "n9 [label=\"power\"];", "n8 -> n9;",
+            "n10 [label=\"factor\"];", "n9 -> n10;",
+            "n11 [label=\"7\", shape=box];", "n10 -> n11;",
+            "n12 [label=\"power_tail\"];", "n9 -> n12;",
+            "n13 [label=\"factor_tail\"];", "n8 -> n13;",
+            "n14 [label=\"term_tail\"];", "n7 -> n14;",
+            "n15 [label=\"bit_tail\"];", "n6 -> n15;",
+            "n16 [label=\"stmt_list\"];", "n1 -> n16;",
+            "n17 [label=\"$$\", shape=box];", "n0 -> n17;",
+        ];
+        let lines: Vec<&str> = dot.lines().map(|l| l.trim()).collect();
+        assert_eq!(lines[0], "digraph {");
+        assert_eq!(&lines[2..lines.len() - 1], &expected[..]);
+        assert_eq!(lines[lines.len() - 1], "}");
+    }
+
+    #[test]
+    fn quotes_labels() {
+        let mut tree = ParseTree::new();
+        tree.nonterminal("write_arg", 1);
+        tree.terminal("say \"hi\"\n");
+        assert!(tree.to_dot().contains(r#"n1 [label="say \"hi\"\n", shape=box];"#));
+    }
+}
@@ -1,587 +1,1719 @@
-/*****************************************************************************
-    Complete recursive descent parser for the calculator language.
-    Builds on figure 2.16 in the text.  Prints a trace of productions
-    predicted and tokens matched.  Does no error recovery: prints
-    "syntax error" and dies (Rust panic) on invalid input.
-
-    (c) Michael L. Scott, 2023
-    For use by students in CSC 2/454 at the University of Rochester,
-    during the Fall 2023 term.  All other use requires written
-    permission of the author.
-
-    The bulk of the provided code consists of methods for three structs,
-    which function much like classes in an OO language:
-    Input
-        buffers stdin a line at a time and provides the scanner
-        w/ characters
-    Scanner
-        peeks ahead one character and provides the parser w/ tokens
-    Parser
-        peeks ahead one token and checks syntax of calculator program
- *****************************************************************************/
-
 ///////////////////////////////////////////////////////////////////////////////
-//  Input buffering
-//
-//  Provides the scanner with characters of stdin, one at a time,
-//  tagged with source line and column.
-//
-//  Does not assume input is ASCII, but iterates over Unicode codepoints,
-//  not graphemes, so diacritics are returned as separate characters.
+//  Parser
+//  Recursive descent.
+//  Epsilon productions are predicted using global FOLLOW sets.
+//  One token of lookahead suffices, except to tell a chained assignment
+//  (a := b := 1) from an expression (a := b + 1), and an increment,
+//  decrement, or compound assignment (x++, x--, x += 2) from a plain
+//  assignment; there the parser peeks at the token after next.
+//  Each syntax tree node gets the span from its first token through its
+//  last, so an operator's spans both operands.
+//  Syntax errors propagate (via Result) out of the statement they occur
+//  in; the parser records them and skips ahead to a token in FOLLOW(stmt)
+//  (panic-mode recovery).  Lexical errors are recorded as well, and the
+//  offending characters ignored.  Once max_errors have been recorded,
+//  the parser gives up rather than recovering again.
 //
 
-mod input {
-    use std::io;
-    use std::cmp::max;
+use crate::scanner::Scanner;
+use crate::scanner::TokTp;
+use crate::scanner::Token;
+use crate::span::Span;
+use crate::ast::*;
+use crate::error::{self, CalcError};
+use crate::parse_tree::ParseTree;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::mem;
 
-    pub struct SourceChar {
-        pub ch: char,
-        pub line: usize,    // 1-based
-        pub col: usize,     // 0-based
+// Tokens that can begin a statement or an expression.
+const FIRST_STMT: &[TokTp] = &[TokTp::Ident, TokTp::Read, TokTp::Write,
+    TokTp::Int, TokTp::Real, TokTp::Const, TokTp::If, TokTp::Do, TokTp::Check, TokTp::Assert, TokTp::For];
+const FIRST_EXPR: &[TokTp] = &[TokTp::Ident, TokTp::ILit, TokTp::RLit,
+    TokTp::LParen, TokTp::Minus, TokTp::Trunc, TokTp::Float];
+// FOLLOW(stmt), where recovery resumes after a bad statement.
+const FOLLOW_STMT: &[TokTp] = &[TokTp::Ident, TokTp::Read, TokTp::Write,
+    TokTp::Int, TokTp::Real, TokTp::Const, TokTp::If, TokTp::Do, TokTp::Check, TokTp::Assert, TokTp::For,
+    TokTp::LBrace, TokTp::RBrace,
+    TokTp::Semi, TokTp::End, TokTp::Elsif, TokTp::Else, TokTp::Fi, TokTp::Od];
+// FOLLOW(expr): FOLLOW(stmt), plus whatever can come after an expression
+// inside a statement -- a comparison operator, the end of a parenthesized
+// expression or write list, and the to/do of a for loop.
+const FOLLOW_EXPR: &[TokTp] = &[TokTp::Ident, TokTp::Read, TokTp::Write,
+    TokTp::Int, TokTp::Real, TokTp::Const, TokTp::If, TokTp::Do, TokTp::Check, TokTp::Assert, TokTp::For,
+    TokTp::LBrace, TokTp::RBrace,
+    TokTp::Semi, TokTp::End, TokTp::Elsif, TokTp::Else, TokTp::Fi, TokTp::Od,
+    TokTp::Greater, TokTp::Lesser, TokTp::EqualTo, TokTp::NEqualTo,
+    TokTp::GreaterEq, TokTp::LesserEq, TokTp::RParen, TokTp::Comma, TokTp::To,
+    TokTp::Question, TokTp::Colon];
+// How many errors to record before giving up, unless set_max_errors()
+// says otherwise.
+pub const DEFAULT_MAX_ERRORS: usize = 20;
+const COMP_OPS: &[TokTp] = &[TokTp::Greater, TokTp::Lesser, TokTp::EqualTo, TokTp::NEqualTo,
+    TokTp::GreaterEq, TokTp::LesserEq];
+const BIT_OPS: &[TokTp] = &[TokTp::BitAnd, TokTp::BitOr, TokTp::BitXor];
+
+// One step of the trace: a production predicted, as "lhs --> rhs", or a
+// token matched.  Displays as a line of the printed trace.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceEvent {
+    Predict(String),
+    Match { tp: TokTp, text: String },
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceEvent::Predict(ref production) => write!(f, "predict {}", production),
+            // only tokens that vary show their text
+            TraceEvent::Match { ref tp, ref text } => match *tp {
+                TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::SLit => {
+                    write!(f, "matched {:?}: {}", tp, text)
+                }
+                _ => write!(f, "matched {:?}", tp),
+            },
+        }
     }
+}
 
-    pub const EOF: char = '\x04';   // ^D sentinel
-    const NL:  char = '\x0a';   // ^J
+// A summary of the derivation: how many productions were predicted and
+// tokens matched, and how deeply productions nested (the height of the
+// parse tree, not counting its leaves).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseStats {
+    pub productions_predicted: usize,
+    pub tokens_matched: usize,
+    pub max_depth: usize,
+}
 
-    // Strangely, Rust's standard str and String types don't provide an easy
-    // and efficient way to inspect their last character.  This adds one.
-    trait StringEnd {
-        fn last_char(&self) -> Option<char>;
+pub struct Parser {
+    scanner: Scanner,
+    next_tok: Token,        // already peeked at
+    after: Option<Token>,   // the one after next_tok, if peeked at too
+    last_span: Span,        // of the token before next_tok
+    errors: Vec<CalcError>,
+    error_lines: HashMap<usize, String>,    // source of lines with errors
+    max_errors: usize,      // 0 for no limit
+    gave_up: bool,          // max_errors reached
+    tree: Option<ParseTree>,
+    stats: ParseStats,
+    open: Vec<usize>,       // symbols still to derive, per production
+    trace: bool,            // report predictions and matches
+    trace_pos: bool,        // with the position of next_tok
+    sink: Box<dyn FnMut(TraceEvent, Option<Span>)>,     // where the trace goes
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
     }
-    impl StringEnd for str {
-        // Return last character of string, if there is one.  Takes O(1) time.
-        fn last_char (self: &str) -> Option<char> {
-            for i in (0..(max(self.len(), 1) - 1)).rev() {
-                if self.is_char_boundary(i) {
-                    return self[i..].chars().next();
-                }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::from_scanner(Scanner::new())
+    }
+
+    pub fn from_scanner(scanner: Scanner) -> Self {
+        Self::with_output(scanner, Box::new(io::stdout()))
+    }
+
+    // Like from_scanner(), but write the trace to out instead of stdout.
+    pub fn with_output(scanner: Scanner, mut out: Box<dyn Write>) -> Self {
+        Self::with_sink(scanner, Box::new(move |event, pos| {
+            let written = match pos {
+                Some(pos) => writeln!(out, "[{}:{}] {}", pos.start_line, pos.start_col, event),
+                None => writeln!(out, "{}", event),
+            };
+            written.expect("can't write parser trace");
+        }))
+    }
+
+    // Like from_scanner(), but hand each trace event to sink instead of
+    // printing it.  The sink isn't given positions; see set_trace_pos().
+    pub fn with_trace_sink(scanner: Scanner, mut sink: impl FnMut(TraceEvent) + 'static)
+            -> Self {
+        Self::with_sink(scanner, Box::new(move |event, _| sink(event)))
+    }
+
+    fn with_sink(scanner: Scanner, sink: Box<dyn FnMut(TraceEvent, Option<Span>)>) -> Self {
+        Self {
+            scanner,
+            next_tok: Token { tp: TokTp::Begin,
+                text: String::new(), span: Span::new(0, 0, 0, 0), index: 0 },
+            after: None,
+            last_span: Span::new(0, 0, 0, 0),
+            errors: Vec::new(),
+            error_lines: HashMap::new(),
+            max_errors: DEFAULT_MAX_ERRORS,
+            gave_up: false,
+            tree: None,
+            stats: ParseStats::default(),
+            open: Vec::new(),
+            trace: true,
+            trace_pos: false,
+            sink,
+        }
+    }
+
+    pub fn scanner(&self) -> &Scanner {
+        &self.scanner
+    }
+
+    // Where the next token to be matched starts: line 0 before parse(),
+    // and the line after the last once it's done.
+    pub fn current_line(&self) -> usize {
+        self.next_tok.span.start_line
+    }
+
+    pub fn current_col(&self) -> usize {
+        self.next_tok.span.start_col
+    }
+
+    // Turn the trace of predictions and matched tokens on (the default)
+    // or off.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    // Start each line of the printed trace with the line and column of
+    // the token being predicted from or matched, as [L:C].  Off by
+    // default.
+    pub fn set_trace_pos(&mut self, trace_pos: bool) {
+        self.trace_pos = trace_pos;
+    }
+
+    // Stop parsing once this many errors have been recorded (0 for no
+    // limit), so a badly broken program doesn't bury the first few under
+    // a cascade of later ones.
+    pub fn set_max_errors(&mut self, max_errors: usize) {
+        self.max_errors = max_errors;
+    }
+
+    // Whether parse() stopped early because it reached max_errors.
+    pub fn gave_up(&self) -> bool {
+        self.gave_up
+    }
+
+    // Collect a parse tree while parsing; see parse_tree().
+    pub fn build_parse_tree(&mut self) {
+        self.tree = Some(ParseTree::new());
+    }
+
+    // The tree built so far, if build_parse_tree() was called.  It is
+    // complete only if parse() succeeded.
+    pub fn parse_tree(&self) -> Option<&ParseTree> {
+        self.tree.as_ref()
+    }
+
+    // Counts for the derivation so far; complete once parse() has
+    // succeeded.
+    pub fn stats(&self) -> ParseStats {
+        self.stats
+    }
+
+    fn trace(&mut self, event: TraceEvent) {
+        if self.trace {
+            let pos = if self.trace_pos { Some(self.next_tok.span) } else { None };
+            (self.sink)(event, pos);
+        }
+    }
+
+    // Trace a prediction, given as "lhs --> rhs".
+    fn predict(&mut self, production: &str) {
+        self.trace(TraceEvent::Predict(production.to_string()));
+        let mut sides = production.splitn(2, " --> ");
+        let lhs = sides.next().unwrap_or("");
+        let rhs_len = match sides.next() {
+            Some("epsilon") | None => 0,
+            Some(rhs) => rhs.split_whitespace().count(),
+        };
+        self.stats.productions_predicted += 1;
+        self.derived();
+        self.open.push(rhs_len);
+        self.stats.max_depth = cmp::max(self.stats.max_depth, self.open.len());
+        self.close_complete();
+        if let Some(ref mut tree) = self.tree {
+            tree.nonterminal(lhs, rhs_len);
+        }
+    }
+
+    // One more symbol of the innermost open production has been derived.
+    // As in the parse tree, the derivation is leftmost, so that's where
+    // each prediction or match belongs.
+    fn derived(&mut self) {
+        if let Some(missing) = self.open.last_mut() {
+            *missing -= 1;
+        }
+    }
+
+    fn close_complete(&mut self) {
+        while let Some(&0) = self.open.last() {
+            self.open.pop();
+        }
+    }
+
+    // I'd call this "match", but that's a keyword.  Returns the matched
+    // token so callers can keep its text and position.
+    fn eat(&mut self, expected: TokTp) -> Result<Token, CalcError> {
+        if self.next_tok.tp == expected {
+            let event = TraceEvent::Match { tp: expected, text: self.next_tok.text.clone() };
+            self.trace(event);
+            self.stats.tokens_matched += 1;
+            self.derived();
+            self.close_complete();
+            if let Some(ref mut tree) = self.tree {
+                tree.terminal(if expected == TokTp::End { "$$" } else { &self.next_tok.text });
             }
-            return None;
+            Ok(self.advance())
+        } else {
+            Err(self.unexpected(vec![expected]))
         }
     }
 
-    pub struct Input {
-        buf: String,
-        line: usize,
-        next_col: usize,    // index of next unread character (or end of line)
+    // Move on to the next token, returning the current one.  Lexical
+    // errors are recorded and the bad input skipped, as are comments.
+    fn advance(&mut self) -> Token {
+        let next = match self.after.take() {
+            Some(tok) => tok,
+            None => self.scan(),
+        };
+        let tok = mem::replace(&mut self.next_tok, next);
+        self.last_span = tok.span;
+        tok
+    }
+
+    // From start through the last token matched, for a node that began
+    // at start.
+    fn span_from(&self, start: Span) -> Span {
+        Span::merge(start, self.last_span)
     }
 
-    impl Input {
-        pub fn new() -> Self {
-            Self {
-                buf: String::new(),     // empty zero-th line
-                line: 0,
-                next_col: 0,
+    // The type of the token after next_tok, which stays where it is.
+    fn peek2(&mut self) -> TokTp {
+        if self.after.is_none() {
+            self.after = Some(self.scan());
+        }
+        self.after.as_ref().unwrap().tp
+    }
+
+    fn scan(&mut self) -> Token {
+        loop {
+            match self.scanner.scan() {
+                Ok(ref tok) if tok.tp == TokTp::Comment => {}
+                Ok(tok) => return tok,
+                Err(e) => self.report(e),
             }
         }
+    }
+
+    fn unexpected(&self, expected: Vec<TokTp>) -> CalcError {
+        CalcError::Syntax {
+            line: self.next_tok.span.start_line,
+            col: self.next_tok.span.start_col,
+            index: self.next_tok.index,
+            expected,
+            found: self.next_tok.tp,
+        }
+    }
+
+    // Record an error, keeping the text of its line (still the one being
+    // scanned) for diagnostic().  Past max_errors, errors are dropped.
+    fn report(&mut self, error: CalcError) {
+        if self.gave_up {
+            return;
+        }
+        let (line, text) = self.scanner.input().current_line();
+        if line == error.line() && !text.is_empty() {
+            self.error_lines.entry(line).or_insert_with(|| text.to_string());
+        }
+        self.errors.push(error);
+        self.gave_up = self.errors.len() == self.max_errors;
+    }
 
-        // getc() is a lot like Iterator::next(), but it doesn't return an Option.
-        // Instead, it returns a sentinel (EOF) at end of file.  This relieves the
-        // scanner of the need to call next().unwrap_or(SourceChar{ EOF, _, _ })
-        pub fn getc(&mut self) -> SourceChar {
-            loop {
-                let col = self.next_col;    // column of char we will be returning
+    // Panic-mode recovery: record the error, then discard tokens until
+    // one that can follow the failed construct (or end of input).  Fails
+    // instead, ending the parse, once there have been too many errors.
+    fn recover(&mut self, error: CalcError, follow: &[TokTp]) -> Result<(), CalcError> {
+        self.report(error.clone());
+        if self.gave_up {
+            return Err(error);
+        }
+        while self.next_tok.tp != TokTp::End && !follow.contains(&self.next_tok.tp) {
+            self.advance();
+        }
+        Ok(())
+    }
+
+    // main entry point.  Errors inside a statement are recorded and
+    // parsing resumes at the next statement; the first error is
+    // returned and all of them are available from errors().
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, CalcError> {
+        self.advance();
+        let result = self.program();
+        if let Err(ref e) = result {
+            self.report(e.clone());
+        }
+        match self.errors.first() {
+            Some(e) => Err(e.clone()),
+            None => result,
+        }
+    }
+
+    // The error's message, followed by its line of source with a caret
+    // under the column, if it has one and the line is known (it is for
+    // errors this parser reported, and for any line the input kept; see
+    // Input::set_keep_lines()).
+    pub fn diagnostic(&self, e: &CalcError) -> String {
+        let text = self.error_lines.get(&e.line()).map(String::as_str)
+            .or_else(|| self.scanner.input().line_text(e.line()));
+        match (e.col(), text) {
+            (Some(col), Some(text)) => error::format_diagnostic(text, col,
+                self.scanner.input().tab_width(), &e.to_string()),
+            _ => e.to_string(),
+        }
+    }
 
-                // use iterator once to get the next UTF8 char
-                if let Some(ch) = self.buf[col..].chars().next() {
-                    // Find start of next character (might not be at
-                    // self.next_col if previous returned character was
-                    // more than a single byte)
-                    if ch != EOF {
-                        loop {
-                            self.next_col += 1;
-                            if self.buf.is_char_boundary(self.next_col) { break; }
-                        }
-                    }
-                    return SourceChar { ch, line: self.line, col };
+    pub fn errors(&self) -> &[CalcError] {
+        &self.errors
+    }
+
+    fn program(&mut self) -> Result<Vec<Stmt>, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::Read | TokTp::Write | TokTp::End | TokTp::Int | TokTp::Real | TokTp::If | TokTp::Do | TokTp::Check
+            | TokTp::Assert | TokTp::For | TokTp::Const | TokTp::Semi | TokTp::LBrace => {
+                self.predict("program --> stmt_list $$");
+                let mut stmts = Vec::new();
+                self.stmt_list(&mut stmts)?;
+                self.eat(TokTp::End)?;
+                Ok(stmts)
+            }
+            _ => Err(self.unexpected([FIRST_STMT,
+                &[TokTp::Semi, TokTp::LBrace, TokTp::End]].concat())),
+        }
+    }
+
+    // Appends the statements it parses to stmts.  Semicolons are optional
+    // separators that mean nothing, so any number of them may come
+    // before, between, or after statements.  Braces group statements
+    // (typically a whole if or loop body) for readability, and mean
+    // nothing either: they don't start a new scope.
+    fn stmt_list(&mut self, stmts: &mut Vec<Stmt>) -> Result<(), CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::Read | TokTp::Write | TokTp::Int | TokTp::Real | TokTp::If | TokTp::Do | TokTp::Check
+            | TokTp::Assert | TokTp::For | TokTp::Const => {
+                self.predict("stmt_list --> stmt stmt_list");
+                match self.stmt() {
+                    Ok(stmt) => stmts.push(stmt),
+                    Err(e) => self.recover(e, FOLLOW_STMT)?,
                 }
-                // else get a new line, if there is one
-                self.buf.clear();
-                let count = io::stdin().read_line(&mut self.buf)
-                    .expect("Can't read stdin!");
-                if count == 0 {     // no more lines!
-                    self.buf.push(EOF);
-                } else if self.buf.last_char().unwrap_or(' ') != NL {
-                    // line ended abruptly (presumably it's the last one); add a NL
-                    self.buf.push(NL);
+                self.stmt_list(stmts)
+            }
+            TokTp::Semi => {
+                self.predict("stmt_list --> semi stmt_list");
+                self.eat(TokTp::Semi)?;
+                self.stmt_list(stmts)
+            }
+            TokTp::LBrace => {
+                self.predict("stmt_list --> lbrace stmt_list rbrace stmt_list");
+                let open = self.eat(TokTp::LBrace)?;
+                self.stmt_list(stmts)?;
+                if self.next_tok.tp != TokTp::RBrace {
+                    return Err(CalcError::Unmatched {
+                        open: TokTp::LBrace,
+                        open_line: open.span.start_line,
+                        found: self.next_tok.tp,
+                        line: self.next_tok.span.start_line,
+                        col: self.next_tok.span.start_col,
+                    });
                 }
-                self.line += 1;
-                self.next_col = 0;
+                self.eat(TokTp::RBrace)?;
+                self.stmt_list(stmts)
+            }
+            TokTp::End | TokTp::Elsif | TokTp::Else | TokTp::Fi | TokTp::Od | TokTp::RBrace => {
+                self.predict("stmt_list --> epsilon");
+                Ok(())
             }
+            _ => Err(self.unexpected([FIRST_STMT, &[TokTp::Semi, TokTp::LBrace, TokTp::End,
+                TokTp::Elsif, TokTp::Else, TokTp::Fi, TokTp::Od, TokTp::RBrace]].concat())),
         }
+    }
 
-    } // end impl Input
+    // The type is optional: a bare `read x` reads into an already declared
+    // variable and keeps its type, so epsilon is predicted on the ident.
+    fn types(&mut self) -> Result<Option<Type>, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Int => {
+                self.predict("type --> int");
+                self.eat(TokTp::Int)?;
+                Ok(Some(Type::Int))
+            }
+            TokTp::Real => {
+                self.predict("type --> real");
+                self.eat(TokTp::Real)?;
+                Ok(Some(Type::Real))
+            }
+            TokTp::Ident => {
+                self.predict("type --> epsilon");
+                Ok(None)
+            }
+            _ => Err(self.unexpected(vec![TokTp::Int, TokTp::Real, TokTp::Ident])),
+        }
+    }
 
-} // end mod input
+    // The right-hand side of :=, which may itself be an assignment.
+    fn assign_value(&mut self) -> Result<Expr, CalcError> {
+        if self.next_tok.tp == TokTp::Ident && self.peek2() == TokTp::Gets {
+            self.predict("assign_value --> ident gets assign_value");
+            let tok = self.eat(TokTp::Ident)?;
+            self.eat(TokTp::Gets)?;
+            let value = self.assign_value()?;
+            let span = Span::merge(tok.span, value.span());
+            return Ok(Expr::Assign { name: tok.text, value: Box::new(value), span });
+        }
+        self.predict("assign_value --> expr");
+        self.expr()
+    }
 
-///////////////////////////////////////////////////////////////////////////////
-//  Scanner
-//
-//  Literals are strings of ASCII digits.
-//  Identifiers are strings of Unicode alphabetics.
-//
-//  White space characters are tossed (no tokens contain such characters).
-//  Since line feeds are white space, no token spans a line boundary.
-//
+    fn comp(&mut self) -> Result<Cond, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("comp --> expr comp_op expr");
+                let lhs = self.expr()?;
+                let op = self.comp_op()?;
+                let rhs = self.expr()?;
+                Ok(Cond { lhs, op, rhs })
+            }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
+        }
+    }
 
-mod scanner {
-    use crate::input::Input;
-    use crate::input::SourceChar;
-    use crate::input::EOF;
-
-    #[derive(PartialEq, Debug)]
-        // allow enum values to be compared for equality and to be (debug) printed
-    pub enum TokTp {Begin, Read, Write, Ident, ILit, RLit, Gets, Greater, Lesser, EqualTo, NEqualTo, GreaterEq, LesserEq,
-        If, Fi, Do, Od, Check, Int, Real, Trunc, Float, Plus, Minus, Times, DivBy, LParen, RParen, End} //do we need to add i_lit and r_lit or is literal good enough?
-        // Begin is a dummy value with which to prime the constructor.
-    #[derive(Debug)]
-    pub struct Token {
-        pub tp: TokTp,
-        pub text: String,
-        pub line: usize,
-        #[allow(dead_code)]     // suppress warning if col is not used
-            pub col: usize,
-    }
-
-    pub struct Scanner {
-        input: Input,
-        next_char: SourceChar,      // already peeked at
-    }
-
-    impl Scanner {
-        pub fn new() -> Self {
-            Self {
-                input: Input::new(),
-                next_char: SourceChar { ch:' ', line: 0, col: 0 },
-            }
-        }
-
-        // scan, like Token::getc, is a lot like Iterator::next(), but it doesn't
-        // return an Option.  Instead, it returns a sentinel (TokTp:End)
-        // at end of file.  This relieves the parser of the need to call
-        // next().unwrap_or(Token{ End, _, _, _ })
-        pub fn scan(&mut self) -> Token {
-            let mut text = String::new();
-            while self.next_char.ch.is_whitespace() {
-                self.next_char = self.input.getc();
-            }
-            let col = self.next_char.col;
-            let line = self.next_char.line;
-            if self.next_char.ch == EOF {
-                return Token { tp: TokTp::End, text, line, col };
-            }
-            if self.next_char.ch.is_alphabetic() {
-                loop {
-                    text.push(self.next_char.ch);
-                    self.next_char = self.input.getc();
-                    if !(self.next_char.ch == '_' ||
-                         self.next_char.ch.is_alphanumeric()) { break; }
-                }
-                if text == "read" {
-                    return Token { tp: TokTp::Read, text, line, col };
-                }
-                if text == "write" {
-                    return Token { tp: TokTp::Write, text, line, col };
-                }
-                if text == "if" {
-                    return Token { tp: TokTp::If, text, line, col };
-                }
-                if text == "fi" {
-                    return Token { tp: TokTp::Fi, text, line, col };
-                }
-                if text == "do" {
-                    return Token { tp: TokTp::Do, text, line, col };
-                }
-                if text == "od" {
-                    return Token { tp: TokTp::Od, text, line, col };
-                }
-                if text == "int" {
-                    return Token { tp: TokTp::Int, text, line, col };
+    fn stmt(&mut self) -> Result<Stmt, CalcError> {
+        let start = self.next_tok.span;
+        match self.next_tok.tp {
+            TokTp::Ident => {
+                // x++ is x := x + 1, x -= e is x := x - (e), and so on
+                let update = match self.peek2() {
+                    TokTp::Incr => Some((BinOp::Add, "stmt --> ident incr")),
+                    TokTp::Decr => Some((BinOp::Sub, "stmt --> ident decr")),
+                    TokTp::PlusEq => Some((BinOp::Add, "stmt --> ident plus_eq expr")),
+                    TokTp::MinusEq => Some((BinOp::Sub, "stmt --> ident minus_eq expr")),
+                    TokTp::TimesEq => Some((BinOp::Mul, "stmt --> ident times_eq expr")),
+                    TokTp::DivEq => Some((BinOp::Div, "stmt --> ident div_eq expr")),
+                    _ => None,
+                };
+                if let Some((op, production)) = update {
+                    self.predict(production);
+                    let tok = self.eat(TokTp::Ident)?;
+                    let op_tp = self.next_tok.tp;
+                    let op_span = self.eat(op_tp)?.span;
+                    let rhs = match op_tp {
+                        TokTp::Incr | TokTp::Decr => Expr::ILit(1, op_span),
+                        _ => self.expr()?,
+                    };
+                    let var = Expr::Ident { name: tok.text.clone(), span: tok.span };
+                    let expr = Expr::binary(op, var, rhs, op_span);
+                    return Ok(Stmt::Assign { name: tok.text, expr, span: self.span_from(start) });
                 }
-                if text == "real" {
-                    return Token { tp: TokTp::Real, text, line, col };
-                }
-                if text == "trunc" {
-                    return Token { tp: TokTp::Trunc, text, line, col };
-                }
-                if text == "float" {
-                    return Token { tp: TokTp::Float, text, line, col };
+                self.predict("stmt --> ident gets assign_value");
+                let name = self.eat(TokTp::Ident)?.text;
+                self.eat(TokTp::Gets)?;
+                let expr = self.assign_value()?;
+                Ok(Stmt::Assign { name, expr, span: self.span_from(start) })
+            }
+            TokTp::Read => {
+                self.predict("stmt --> read TP ident ident_tail");
+                self.eat(TokTp::Read)?;
+                let tp = self.types()?;
+                let mut names = vec![self.eat(TokTp::Ident)?.text];
+                while self.next_tok.tp == TokTp::Comma {
+                    self.predict("ident_tail --> comma ident ident_tail");
+                    self.eat(TokTp::Comma)?;
+                    names.push(self.eat(TokTp::Ident)?.text);
                 }
-                if text == "check" {
-                    return Token { tp: TokTp::Check, text, line, col };
+                self.predict("ident_tail --> epsilon");
+                Ok(Stmt::Read { tp, names, span: self.span_from(start) })
+            }
+            TokTp::Write => {
+                self.predict("stmt --> write write_arg write_arg_tail");
+                self.eat(TokTp::Write)?;
+                let mut args = vec![self.write_arg()?];
+                while self.next_tok.tp == TokTp::Comma {
+                    self.predict("write_arg_tail --> comma write_arg write_arg_tail");
+                    self.eat(TokTp::Comma)?;
+                    args.push(self.write_arg()?);
                 }
-                // are these text checks correct, also do I need to create one for i_lit, r_Lit?
-                return Token { tp: TokTp::Ident, text, line, col };
-            }
-            //WE NEED TO MAKE THIS RECOGNIZE INTS AND REALS
-            if self.next_char.ch.is_ascii_digit() {
-                loop {
-                    text.push(self.next_char.ch);
-                    self.next_char = self.input.getc();
-                    if !self.next_char.ch.is_ascii_digit() && self.next_char.ch != '.' { break; }
+                self.predict("write_arg_tail --> epsilon");
+                Ok(Stmt::Write { args, span: self.span_from(start) })
+            }
+            TokTp::If => {
+                self.predict("stmt --> if comp stmt_list elsif_part else_part fi");
+                self.eat(TokTp::If)?;
+                let cond = self.comp()?;
+                let mut body = Vec::new();
+                self.stmt_list(&mut body)?;
+                let mut branches = vec![(cond, body)];
+                while self.next_tok.tp == TokTp::Elsif {
+                    self.predict("elsif_part --> elsif comp stmt_list elsif_part");
+                    self.eat(TokTp::Elsif)?;
+                    let cond = self.comp()?;
+                    let mut body = Vec::new();
+                    self.stmt_list(&mut body)?;
+                    branches.push((cond, body));
                 }
-                return Token { tp: TokTp::ILit, text, line, col };
-            }
-            if self.next_char.ch == '.' {
-                
-            }
-            text.push(self.next_char.ch);
-            let c = self.next_char.ch;
-            self.next_char = self.input.getc();
-            match c {
-                ':' => {
-                        if self.next_char.ch != '=' {
-                            panic!("extected '=' after ':', got '{}' (0x{:x})",
-                                self.next_char.ch, self.next_char.ch as u32);
-                        }
-                        text.push('=');
-                        self.next_char = self.input.getc();
-                        return Token { tp: TokTp::Gets, text, line, col };
-                    }
-                '=' => {
-                        if self.next_char.ch != '=' {
-                            panic!("extected '=' after '=', got '{}' (0x{:x})",
-                                self.next_char.ch, self.next_char.ch as u32);
-                        }
-                        text.push('=');
-                        self.next_char = self.input.getc();
-                        return Token { tp: TokTp::EqualTo, text, line, col };
-                    }
-                '!' => {
-                        if self.next_char.ch != '=' {
-                            panic!("extected '=' after '!', got '{}' (0x{:x})",
-                                self.next_char.ch, self.next_char.ch as u32);
-                        }
-                        text.push('=');
-                        self.next_char = self.input.getc();
-                        return Token { tp: TokTp::NEqualTo, text, line, col };
-                    }
-                '<' => {
-                        if self.next_char.ch == '=' {
-                            text.push('=');
-                            self.next_char = self.input.getc();
-                            return Token { tp: TokTp::LesserEq, text, line, col};
-                        }
-                        return Token { tp: TokTp::Lesser, text, line, col };
-                    }
-                '>' => {
-                        if self.next_char.ch == '=' {
-                            text.push('=');
-                            self.next_char = self.input.getc();
-                            return Token { tp: TokTp::GreaterEq, text, line, col};
-                        }
-                        return Token { tp: TokTp::Greater, text, line, col };
-                    }
-                    // did i add these correctly?
-                '+' => return Token { tp: TokTp::Plus, text, line, col},
-                '-' => return Token { tp: TokTp::Minus, text, line, col },
-                '*' => return Token { tp: TokTp::Times, text, line, col },
-                '/' => return Token { tp: TokTp::DivBy, text, line, col },
-                '(' => return Token { tp: TokTp::LParen, text, line, col },
-                ')' => return Token { tp: TokTp::RParen, text, line, col },
-                _ =>   panic!("unexpected character '{}' (0x{:x})",
-                            self.next_char.ch, self.next_char.ch as u32),
-            }
-        }
-
-    } // end impl Scanner
-
-} // end mod scanner
+                self.predict("elsif_part --> epsilon");
+                let else_body = if self.next_tok.tp == TokTp::Else {
+                    self.predict("else_part --> else stmt_list");
+                    self.eat(TokTp::Else)?;
+                    let mut else_body = Vec::new();
+                    self.stmt_list(&mut else_body)?;
+                    Some(else_body)
+                } else {
+                    self.predict("else_part --> epsilon");
+                    None
+                };
+                self.eat(TokTp::Fi)?;
+                Ok(Stmt::If { branches, else_body, span: self.span_from(start) })
+            }
+            TokTp::Do => {
+                self.predict("stmt --> do stmt_list od");
+                self.eat(TokTp::Do)?;
+                let mut body = Vec::new();
+                self.stmt_list(&mut body)?;
+                self.eat(TokTp::Od)?;
+                Ok(Stmt::Do { body, span: self.span_from(start) })
+            }
+            TokTp::For => {
+                self.predict("stmt --> for ident gets expr to expr do stmt_list od");
+                self.eat(TokTp::For)?;
+                let var = self.eat(TokTp::Ident)?.text;
+                self.eat(TokTp::Gets)?;
+                let from = self.expr()?;
+                self.eat(TokTp::To)?;
+                let to = self.expr()?;
+                self.eat(TokTp::Do)?;
+                let mut body = Vec::new();
+                self.stmt_list(&mut body)?;
+                self.eat(TokTp::Od)?;
+                Ok(Stmt::For { var, from, to, body, span: self.span_from(start) })
+            }
+            TokTp::Check => {
+                self.predict("stmt --> check comp");
+                self.eat(TokTp::Check)?;
+                let cond = self.comp()?;
+                Ok(Stmt::Check { cond, span: self.span_from(start) })
+            }
+            TokTp::Assert => {
+                self.predict("stmt --> assert comp");
+                self.eat(TokTp::Assert)?;
+                let cond = self.comp()?;
+                Ok(Stmt::Assert { cond, span: self.span_from(start) })
+            }
+            TokTp::Int => {
+                self.predict("stmt --> int ident init_part");
+                self.eat(TokTp::Int)?;
+                let name = self.eat(TokTp::Ident)?.text;
+                let expr = self.init_part()?;
+                Ok(Stmt::Decl { tp: Type::Int, name, expr, span: self.span_from(start) })
+            }
+            TokTp::Real => {
+                self.predict("stmt --> real ident init_part");
+                self.eat(TokTp::Real)?;
+                let name = self.eat(TokTp::Ident)?.text;
+                let expr = self.init_part()?;
+                Ok(Stmt::Decl { tp: Type::Real, name, expr, span: self.span_from(start) })
+            }
+            TokTp::Const => {
+                self.predict("stmt --> const type ident gets expr");
+                self.eat(TokTp::Const)?;
+                // unlike in a read, the type is required
+                let tp = match self.next_tok.tp {
+                    TokTp::Int | TokTp::Real => self.types()?.expect("types() returns int or real"),
+                    _ => return Err(self.unexpected(vec![TokTp::Int, TokTp::Real])),
+                };
+                let name = self.eat(TokTp::Ident)?.text;
+                self.eat(TokTp::Gets)?;
+                let expr = self.expr()?;
+                Ok(Stmt::Const { tp, name, expr, span: self.span_from(start) })
+            }
+            _ => Err(self.unexpected(FIRST_STMT.to_vec())),
+        }
+    }
 
-///////////////////////////////////////////////////////////////////////////////
-//  Parser
-//  Recursive descent.
-//  Epsilon productions are predicted using global FOLLOW sets.
-//
+    // The initial value of an int or real variable, if it's given one.
+    fn init_part(&mut self) -> Result<Option<Expr>, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Gets => {
+                self.predict("init_part --> gets expr");
+                self.eat(TokTp::Gets)?;
+                Ok(Some(self.expr()?))
+            }
+            tp if FOLLOW_STMT.contains(&tp) => {
+                self.predict("init_part --> epsilon");
+                Ok(None)
+            }
+            _ => Err(self.unexpected([&[TokTp::Gets], FOLLOW_STMT].concat())),
+        }
+    }
 
-mod parser {
-    use crate::scanner::Scanner;
-    use crate::scanner::TokTp;
-    use crate::scanner::Token;
+    fn write_arg(&mut self) -> Result<WriteArg, CalcError> {
+        match self.next_tok.tp {
+            TokTp::SLit => {
+                self.predict("write_arg --> s_lit");
+                let tok = self.eat(TokTp::SLit)?;
+                Ok(WriteArg::Str(tok.text, tok.span))
+            }
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("write_arg --> expr");
+                Ok(WriteArg::Expr(self.expr()?))
+            }
+            _ => Err(self.unexpected([&[TokTp::SLit], FIRST_EXPR].concat())),
+        }
+    }
 
-    pub struct Parser {
-        scanner: Scanner,
-        next_tok: Token,        // already peeked at
+    fn expr(&mut self) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("expr --> sum bit_tail");
+                let lhs = self.sum()?;
+                self.bit_tail(lhs)
+            }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
+        }
     }
 
-    impl Parser {
-        pub fn new() -> Self {
-            Self {
-                scanner: Scanner::new(),
-                next_tok: Token { tp: TokTp::Begin,
-                    text: String::new(), line: 0, col: 0 },
+    // Like term_tail, one level looser: a & b | c is (a & b) | c.
+    fn bit_tail(&mut self, lhs: Expr) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::BitAnd | TokTp::BitOr | TokTp::BitXor => {
+                self.predict("bit_tail --> bit_op sum bit_tail");
+                let op_span = self.next_tok.span;
+                let op = self.bit_op()?;
+                let rhs = self.sum()?;
+                self.bit_tail(Expr::binary(op, lhs, rhs, op_span))
+            }
+            _ if FOLLOW_EXPR.contains(&self.next_tok.tp) => {
+                self.predict("bit_tail --> epsilon");
+                Ok(lhs)
             }
+            _ => Err(self.unexpected([BIT_OPS, FOLLOW_EXPR].concat())),
         }
+    }
 
-        // I'd call this "match", but that's a keyword.
-        fn eat(&mut self, expected: TokTp) {
-            if self.next_tok.tp == expected {
-                print!("matched {:?}", expected);
-                if expected == TokTp::Ident || expected == TokTp::ILit || expected == TokTp::RLit {
-                    print!(": {}", self.next_tok.text);
-                }
-                println!("");
-                self.next_tok = self.scanner.scan();
-            } else {
-                panic!("syntax error on line {}", self.next_tok.line);
+    fn sum(&mut self) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("sum --> term term_tail");
+                let lhs = self.term()?;
+                self.term_tail(lhs)
             }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
         }
+    }
 
-        // main entry point
-        pub fn parse(&mut self) {
-            self.next_tok = self.scanner.scan();
-            self.program();
+    fn term(&mut self) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("term --> power factor_tail");
+                let lhs = self.power()?;
+                self.factor_tail(lhs)
+            }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
         }
+    }
 
-        fn program(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident | TokTp::Read | TokTp::Write | TokTp::End | TokTp::Int | TokTp::Real | TokTp::If | TokTp::Do | TokTp::Check => {
-                    println!("predict program --> stmt_list $$");
-                    self.stmt_list();
-                    self.eat (TokTp::End)
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    // lhs is everything to the left of the tail, so operators group
+    // to the left: a - b - c is (a - b) - c.
+    fn term_tail(&mut self, lhs: Expr) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Plus | TokTp::Minus => {
+                self.predict("term_tail --> add_op term term_tail");
+                let op_span = self.next_tok.span;
+                let op = self.add_op()?;
+                let rhs = self.term()?;
+                self.term_tail(Expr::binary(op, lhs, rhs, op_span))
+            }
+            // Predict epsilon only on FOLLOW(term_tail) = FOLLOW(sum), the
+            // bitwise operators and FOLLOW(expr), so a stray token is
+            // reported here rather than by whoever runs next.
+            TokTp::BitAnd | TokTp::BitOr | TokTp::BitXor => {
+                self.predict("term_tail --> epsilon");
+                Ok(lhs)
+            }
+            _ if FOLLOW_EXPR.contains(&self.next_tok.tp) => {
+                self.predict("term_tail --> epsilon");
+                Ok(lhs)
             }
+            _ => Err(self.unexpected([&[TokTp::Plus, TokTp::Minus], BIT_OPS, FOLLOW_EXPR]
+                .concat())),
         }
+    }
 
-        fn stmt_list(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident | TokTp::Read | TokTp::Write | TokTp::Int | TokTp::Real | TokTp::If | TokTp::Do | TokTp::Check => {
-                    println!("predict stmt_list --> stmt stmt_list");
-                    self.stmt();
-                    self.stmt_list();
-                }
-                TokTp::End => println!("predict stmt_list --> epsilon"),
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn factor(&mut self) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident => {
+                self.predict("factor --> ident");
+                let tok = self.eat(TokTp::Ident)?;
+                Ok(Expr::Ident { name: tok.text, span: tok.span })
             }
+            TokTp::ILit => {
+                self.predict("factor --> i_lit");
+                let tok = self.eat(TokTp::ILit)?;
+                Ok(Expr::ILit(tok.text.parse().expect("scanner checks i_lit range"), tok.span))
+            }
+            TokTp::RLit => {
+                self.predict("factor --> r_lit");
+                let tok = self.eat(TokTp::RLit)?;
+                Ok(Expr::RLit(tok.text.parse().expect("scanner checks r_lit syntax"), tok.span))
+            }
+            TokTp::LParen => {
+                self.predict("factor --> lparen expr cond_tail rparen");
+                let start = self.eat(TokTp::LParen)?.span;
+                let expr = self.expr()?;
+                self.cond_tail(expr, start)
+            }
+            TokTp::Minus => {
+                self.predict("factor --> minus factor");
+                let start = self.eat(TokTp::Minus)?.span;
+                let operand = self.factor()?;
+                Ok(Expr::Neg { operand: Box::new(operand), span: self.span_from(start) })
+            }
+            TokTp::Trunc => {
+                self.predict("factor --> trunc lparen expr rparen");
+                let start = self.eat(TokTp::Trunc)?.span;
+                self.eat(TokTp::LParen)?;
+                let expr = self.expr()?;
+                self.eat(TokTp::RParen)?;
+                Ok(Expr::Trunc(Box::new(expr), self.span_from(start)))
+            }
+            TokTp::Float => {
+                self.predict("factor --> float lparen expr rparen");
+                let start = self.eat(TokTp::Float)?.span;
+                self.eat(TokTp::LParen)?;
+                let expr = self.expr()?;
+                self.eat(TokTp::RParen)?;
+                Ok(Expr::Float(Box::new(expr), self.span_from(start)))
+            }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
         }
+    }
 
-        fn types(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Int => {
-                    println!("predict type --> int");
-                    self.eat(TokTp::Int);
-                }
-                TokTp::Real => {
-                    println!("predict type --> real");
-                    self.eat(TokTp::Real);
-                }
-                TokTp::End => println!("predict type --> epsilon"),
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn factor_tail(&mut self, lhs: Expr) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Times | TokTp::DivBy | TokTp::Mod => {
+                self.predict("factor_tail --> mul_op power factor_tail");
+                let op_span = self.next_tok.span;
+                let op = self.mul_op()?;
+                let rhs = self.power()?;
+                self.factor_tail(Expr::binary(op, lhs, rhs, op_span))
+            }
+            TokTp::Plus | TokTp::Minus | TokTp::BitAnd | TokTp::BitOr | TokTp::BitXor => {
+                self.predict("factor_tail --> epsilon");
+                Ok(lhs)
             }
+            _ if FOLLOW_EXPR.contains(&self.next_tok.tp) => {
+                self.predict("factor_tail --> epsilon");
+                Ok(lhs)
+            }
+            _ => Err(self.unexpected([&[TokTp::Times, TokTp::DivBy, TokTp::Mod,
+                TokTp::Plus, TokTp::Minus], BIT_OPS, FOLLOW_EXPR].concat())),
         }
+    }
 
-        fn comp(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen => {             //fix the first set
-                    println!("predict comp --> expr comp_op expr");
-                    self.expr();
-                    self.comp_op();
-                    self.expr();
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn power(&mut self) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen | TokTp::Minus
+            | TokTp::Trunc | TokTp::Float => {
+                self.predict("power --> factor power_tail");
+                let lhs = self.factor()?;
+                self.power_tail(lhs)
             }
+            _ => Err(self.unexpected(FIRST_EXPR.to_vec())),
         }
+    }
 
-        fn stmt(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident => {
-                    println!("predict stmt --> ident gets expr");
-                    self.eat(TokTp::Ident);
-                    self.eat(TokTp::Gets);
-                    self.expr();
-                }
-                TokTp::Read => {
-                    println!("predict stmt --> read TP ident");
-                    self.eat(TokTp::Read);
-                    self.types(); // added TP
-                    self.eat(TokTp::Ident);
-                }
-                TokTp::Write => {
-                    println!("predict stmt --> write expr");
-                    self.eat(TokTp::Write);
-                    self.expr();
-                }
-                TokTp::If => {
-                    println!("predict stmt --> if comp stmt_list fi");
-                    self.eat(TokTp::If);
-                    self.comp();
-                    self.stmt_list();
-                    self.eat(TokTp::Fi)
-                }
-                TokTp::Do => {
-                    println!("predict stmt --> do stmt_list od");
-                    self.eat(TokTp::Do);
-                    self.stmt_list();
-                    self.eat(TokTp::Od);
-                }
-                TokTp::Check => {
-                    println!("predict stmt --> check comp");
-                    self.eat(TokTp::Check);
-                    self.comp();
-                }
-                TokTp::Int => {
-                    println!("predict stmt --> int ident gets expr");
-                    self.eat(TokTp::Int);
-                    self.eat(TokTp::Ident);
-                    self.eat(TokTp::Gets);
-                    self.expr();
-                }
-                TokTp::Real => {
-                    println!("predict stmt --> real ident gets expr");
-                    self.eat(TokTp::Real);
-                    self.eat(TokTp::Ident);
-                    self.eat(TokTp::Gets);
-                    self.expr();
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    // Unlike the other tails, this one recurses through power, so **
+    // groups to the right: a ** b ** c is a ** (b ** c).
+    fn power_tail(&mut self, lhs: Expr) -> Result<Expr, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Pow => {
+                self.predict("power_tail --> pow power");
+                let op_span = self.eat(TokTp::Pow)?.span;
+                let rhs = self.power()?;
+                Ok(Expr::binary(BinOp::Pow, lhs, rhs, op_span))
+            }
+            TokTp::Times | TokTp::DivBy | TokTp::Mod | TokTp::Plus | TokTp::Minus
+            | TokTp::BitAnd | TokTp::BitOr | TokTp::BitXor => {
+                self.predict("power_tail --> epsilon");
+                Ok(lhs)
+            }
+            _ if FOLLOW_EXPR.contains(&self.next_tok.tp) => {
+                self.predict("power_tail --> epsilon");
+                Ok(lhs)
             }
+            _ => Err(self.unexpected([&[TokTp::Pow, TokTp::Times, TokTp::DivBy, TokTp::Mod,
+                TokTp::Plus, TokTp::Minus], BIT_OPS, FOLLOW_EXPR].concat())),
         }
+    }
 
-        fn expr(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen => {
-                    println!("predict expr --> term term_tail");
-                    self.term();
-                    self.term_tail();
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    // After "( expr": either the closing parenthesis, or the rest of a
+    // conditional expression whose condition begins with lhs.
+    fn cond_tail(&mut self, lhs: Expr, start: Span) -> Result<Expr, CalcError> {
+        if COMP_OPS.contains(&self.next_tok.tp) {
+            self.predict("cond_tail --> comp_op expr question expr colon expr");
+            let op = self.comp_op()?;
+            let rhs = self.expr()?;
+            self.eat(TokTp::Question)?;
+            let then = self.expr()?;
+            self.eat(TokTp::Colon)?;
+            let otherwise = self.expr()?;
+            self.eat(TokTp::RParen)?;
+            return Ok(Expr::Ternary { cond: Box::new(Cond { lhs, op, rhs }),
+                then: Box::new(then), otherwise: Box::new(otherwise),
+                span: self.span_from(start) });
+        }
+        if self.next_tok.tp == TokTp::RParen {
+            self.predict("cond_tail --> epsilon");
+            self.eat(TokTp::RParen)?;
+            return Ok(lhs);
+        }
+        Err(self.unexpected([COMP_OPS, &[TokTp::RParen]].concat()))
+    }
+
+    fn comp_op(&mut self) -> Result<CompOp, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Greater => {
+                self.predict("comp_op --> greater");
+                self.eat(TokTp::Greater)?;
+                Ok(CompOp::Gt)
+            }
+            TokTp::Lesser => {
+                self.predict("comp_op --> lesser");
+                self.eat(TokTp::Lesser)?;
+                Ok(CompOp::Lt)
             }
+            TokTp::EqualTo => {
+                self.predict("comp_op --> equalto");
+                self.eat(TokTp::EqualTo)?;
+                Ok(CompOp::Eq)
+            }
+            TokTp::NEqualTo => {
+                self.predict("comp_op --> nequalto");
+                self.eat(TokTp::NEqualTo)?;
+                Ok(CompOp::Ne)
+            }
+            TokTp::GreaterEq => {
+                self.predict("comp_op --> greatereq");
+                self.eat(TokTp::GreaterEq)?;
+                Ok(CompOp::Ge)
+            }
+            TokTp::LesserEq => {
+                self.predict("comp_op --> lessereq");
+                self.eat(TokTp::LesserEq)?;
+                Ok(CompOp::Le)
+            }
+            _ => Err(self.unexpected(vec![TokTp::Greater, TokTp::Lesser, TokTp::EqualTo,
+                TokTp::NEqualTo, TokTp::GreaterEq, TokTp::LesserEq])),
         }
+    }
 
-        fn term(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident | TokTp::ILit | TokTp::RLit | TokTp::LParen => {
-                    println!("predict term --> factor factor_tail");
-                    self.factor();
-                    self.factor_tail();
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn add_op(&mut self) -> Result<BinOp, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Plus => {
+                self.predict("add_op --> plus");
+                self.eat(TokTp::Plus)?;
+                Ok(BinOp::Add)
             }
+            TokTp::Minus => {
+                self.predict("add_op --> minus");
+                self.eat(TokTp::Minus)?;
+                Ok(BinOp::Sub)
+            }
+            _ => Err(self.unexpected(vec![TokTp::Plus, TokTp::Minus])),
         }
+    }
 
-        fn term_tail(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Plus | TokTp::Minus => {
-                    println!("predict term_tail --> add_op term term_tail");
-                    self.add_op();
-                    self.term();
-                    self.term_tail();
-                }
-                TokTp::RParen | TokTp::Ident | TokTp::Read | TokTp::Write | TokTp::End => {       // how does this epsilon production work? (compared to the other one above)
-                    println!("predict term_tail --> epsilon");
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn mul_op(&mut self) -> Result<BinOp, CalcError> {
+        match self.next_tok.tp {
+            TokTp::Times => {
+                self.predict("mul_op --> times");
+                self.eat(TokTp::Times)?;
+                Ok(BinOp::Mul)
+            }
+            TokTp::DivBy => {
+                self.predict("mul_op --> div_by");
+                self.eat(TokTp::DivBy)?;
+                Ok(BinOp::Div)
             }
+            TokTp::Mod => {
+                self.predict("mul_op --> mod");
+                self.eat(TokTp::Mod)?;
+                Ok(BinOp::Mod)
+            }
+            _ => Err(self.unexpected(vec![TokTp::Times, TokTp::DivBy, TokTp::Mod])),
         }
+    }
 
-        fn factor(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Ident => {
-                    println!("predict factor --> ident");
-                    self.eat(TokTp::Ident);
-                }
-                TokTp::ILit => {
-                    println!("predict factor --> i_lit");
-                    self.eat(TokTp::ILit);
-                }
-                TokTp::RLit => {
-                    println!("predict factor --> r_lit");
-                    self.eat(TokTp::RLit);
-                }
-                TokTp::LParen => {
-                    println!("predict factor --> lparen expr rparen");
-                    self.eat(TokTp::LParen);
-                    self.expr();
-                    self.eat(TokTp::RParen);
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    fn bit_op(&mut self) -> Result<BinOp, CalcError> {
+        match self.next_tok.tp {
+            TokTp::BitAnd => {
+                self.predict("bit_op --> bit_and");
+                self.eat(TokTp::BitAnd)?;
+                Ok(BinOp::BitAnd)
+            }
+            TokTp::BitOr => {
+                self.predict("bit_op --> bit_or");
+                self.eat(TokTp::BitOr)?;
+                Ok(BinOp::BitOr)
+            }
+            TokTp::BitXor => {
+                self.predict("bit_op --> bit_xor");
+                self.eat(TokTp::BitXor)?;
+                Ok(BinOp::BitXor)
             }
+            _ => Err(self.unexpected(BIT_OPS.to_vec())),
         }
+    }
 
-        fn factor_tail(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Times | TokTp::DivBy => {
-                    println!("predict factor_tail --> mul_op factor factor_tail");
-                    self.mul_op();
-                    self.factor();
-                    self.factor_tail();
-                }
-                TokTp::Plus | TokTp::Minus | TokTp::RParen | TokTp::Ident
-                            | TokTp::Read | TokTp::Write | TokTp::End => {
-                    println!("predict factor_tail --> epsilon");
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+} // end impl Parser
+// HOW DO WE ADD THE I_LIT/R_LIT PRODUCTION?
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::input::Input;
+    use crate::grammar;
+    use crate::pretty::pretty_print;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+
+    // A parser for src with tracing off; the other modules' tests share
+    // this and parse_src.
+    pub(crate) fn parser(src: &str) -> Parser {
+        let mut parser = Parser::from_scanner(Scanner::from_input(Input::from_str(src)));
+        parser.set_trace(false);
+        parser
+    }
+
+    fn parse(src: &str) -> Result<Vec<Stmt>, CalcError> {
+        parser(src).parse()
+    }
+
+    // The statements of src, which must parse.
+    pub(crate) fn parse_src(src: &str) -> Vec<Stmt> {
+        parse(src).unwrap()
+    }
+
+    // Columns col to end_col of line.
+    fn at(line: usize, col: usize, end_col: usize) -> Span {
+        Span::new(line, col, line, end_col)
+    }
+
+    // An identifier or one-digit literal at col of line 1.
+    fn ident(name: &str, col: usize) -> Expr {
+        Expr::Ident { name: name.to_string(), span: at(1, col, col + name.len()) }
+    }
+
+    fn lit(v: i64, col: usize) -> Expr {
+        Expr::ILit(v, at(1, col, col + 1))
+    }
+
+    #[test]
+    fn modulo_at_multiplicative_precedence() {
+        assert!(parse("a := b % c").is_ok());
+        assert!(parse("a := b * c % d + e").is_ok());
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert!(parse("x := -5").is_ok());
+        assert!(parse("x := -(a + b)").is_ok());
+        assert!(parse("x := 3 - -4").is_ok());
+        assert!(parse("write - -x * -2").is_ok());
+        // but -- is a decrement
+        assert!(parse("write --x").is_err());
+    }
+
+    #[test]
+    fn builds_left_associative_tree() {
+        let stmts = parse("x := 1 - 2 - 3").unwrap();
+        let expected = Expr::binary(BinOp::Sub,
+            Expr::binary(BinOp::Sub, lit(1, 5), lit(2, 9), at(1, 7, 8)),
+            lit(3, 13), at(1, 11, 12));
+        assert_eq!(stmts, vec![Stmt::Assign { name: "x".to_string(), expr: expected,
+            span: at(1, 0, 14) }]);
+    }
+
+    #[test]
+    fn power_is_right_associative_and_binds_tightest() {
+        let stmts = parse("x := 2 * 3 ** 4 ** 5").unwrap();
+        let expected = Expr::binary(BinOp::Mul, lit(2, 5),
+            Expr::binary(BinOp::Pow, lit(3, 9),
+                Expr::binary(BinOp::Pow, lit(4, 14), lit(5, 19), at(1, 16, 18)),
+                at(1, 11, 13)),
+            at(1, 7, 8));
+        assert_eq!(stmts, vec![Stmt::Assign { name: "x".to_string(), expr: expected,
+            span: at(1, 0, 20) }]);
+        let stmts = parse("x := 2 * 3").unwrap();
+        assert_eq!(stmts, vec![Stmt::Assign { name: "x".to_string(),
+            expr: Expr::binary(BinOp::Mul, lit(2, 5), lit(3, 9), at(1, 7, 8)),
+            span: at(1, 0, 10) }]);
+    }
+
+    #[test]
+    fn comma_lists() {
+        let stmts = parse("read int a, b write a + 1, \"and\", b").unwrap();
+        assert_eq!(stmts, vec![
+            Stmt::Read { tp: Some(Type::Int), names: vec!["a".to_string(), "b".to_string()],
+                span: at(1, 0, 13) },
+            Stmt::Write { args: vec![
+                WriteArg::Expr(Expr::binary(BinOp::Add, ident("a", 20), lit(1, 24),
+                    at(1, 22, 23))),
+                WriteArg::Str("and".to_string(), at(1, 27, 32)),
+                WriteArg::Expr(ident("b", 34)),
+            ], span: at(1, 14, 35) },
+        ]);
+        assert!(parse("read int a,").is_err());
+        assert!(parse("write 1, , 2").is_err());
+    }
+
+    #[test]
+    fn expressions_end_at_any_follow_token() {
+        let stmts = parse("if a < b\n  x := 1\nfi").unwrap();
+        assert_eq!(stmts, vec![Stmt::If {
+            branches: vec![(
+                Cond { lhs: ident("a", 3), op: CompOp::Lt, rhs: ident("b", 7) },
+                vec![Stmt::Assign { name: "x".to_string(), expr: Expr::ILit(1, at(2, 7, 8)),
+                    span: at(2, 2, 8) }],
+            )],
+            else_body: None,
+            span: Span::new(1, 0, 3, 2),
+        }]);
+        let stmts = parse("do x := 1 od").unwrap();
+        assert_eq!(stmts, vec![Stmt::Do {
+            body: vec![Stmt::Assign { name: "x".to_string(), expr: lit(1, 8),
+                span: at(1, 3, 9) }],
+            span: at(1, 0, 12),
+        }]);
+        assert!(parse("if a * 2 >= (b + 1) ** 2 x := a - 1 else x := b fi").is_ok());
+        assert!(parse("do check x != 0 x := x - 1 od").is_ok());
+        assert!(parse("for i := 1 to n * 2 do write i od").is_ok());
+        assert!(parse("int x := 1 real y := 2.0 check x <= 2").is_ok());
+        // Gets can't follow an expression, so this is caught in term_tail.
+        assert!(parse("x := 1 := 2").is_err());
+    }
+
+    #[test]
+    fn diagnostics_show_the_line() {
+        let mut p = parser("x := 1\ny := * 2\nwrite @\nif a");
+        assert!(p.parse().is_err());
+        let got: Vec<String> = p.errors().iter().map(|e| p.diagnostic(e)).collect();
+        assert_eq!(got.len(), 4);
+        assert!(got[0].ends_with("found Times\n  y := * 2\n       ^"), "{}", got[0]);
+        assert!(got[1].ends_with("col 6\n  write @\n        ^"), "{}", got[1]);
+        assert!(got[2].ends_with("found If\n  if a\n  ^"), "{}", got[2]);
+        // end of input has no line to show
+        assert!(!got[3].contains('\n'), "{}", got[3]);
+
+        // with kept lines, so does any error reported later
+        let mut input = Input::from_str("x := 1\ny := x @ 2\n");
+        input.set_keep_lines(true);
+        let mut p = Parser::from_scanner(Scanner::from_input(input));
+        p.set_trace(false);
+        p.parse().unwrap_err();
+        let later = CalcError::Lex { msg: "late".to_string(), line: 1, col: 2 };
+        assert_eq!(p.diagnostic(&later), "late on line 1, col 2\n  x := 1\n    ^");
+
+        // tabs are expanded as the input counted them
+        let mut input = Input::from_str("x :=\t@ 2\n");
+        input.set_tab_width(4);
+        let mut p = Parser::from_scanner(Scanner::from_input(input));
+        p.set_trace(false);
+        p.parse().unwrap_err();
+        assert_eq!(p.diagnostic(&p.errors()[0]),
+            "unexpected character '@' (0x40) on line 1, col 8\n  x :=    @ 2\n          ^");
+    }
+
+    #[test]
+    fn chained_assignment() {
+        assert_eq!(parse("a := b := c + 1").unwrap(), vec![Stmt::Assign {
+            name: "a".to_string(),
+            expr: Expr::Assign { name: "b".to_string(),
+                value: Box::new(Expr::binary(BinOp::Add, ident("c", 10), lit(1, 14),
+                    at(1, 12, 13))),
+                span: at(1, 5, 15) },
+            span: at(1, 0, 15),
+        }]);
+        // one token of lookahead isn't enough to tell these from the above
+        assert_eq!(parse("a := b + 1 c := 2").unwrap().len(), 2);
+        assert_eq!(parse("a := b\nc := 2").unwrap().len(), 2);
+        assert!(parse("a := b := := 1").is_err());
+        assert!(parse("a := 1 := 2").is_err());
+    }
+
+    #[test]
+    fn conditional_expression() {
+        let stmts = parse("write (a < b ? a : b)").unwrap();
+        assert_eq!(stmts, vec![Stmt::Write { args: vec![WriteArg::Expr(Expr::Ternary {
+            cond: Box::new(Cond { lhs: ident("a", 7), op: CompOp::Lt, rhs: ident("b", 11) }),
+            then: Box::new(ident("a", 15)),
+            otherwise: Box::new(ident("b", 19)),
+            span: at(1, 6, 21),
+        })], span: at(1, 0, 21) }]);
+        assert!(parse("x := (a == 1 ? (b > 2 ? 1 : 2) : 3) + 1").is_ok());
+        // the parentheses are required, and so is the else branch
+        assert!(parse("write a < b ? a : b").is_err());
+        assert!(parse("write (a < b ? a)").is_err());
+        match parse("write (a ? b : c)") {
+            Err(CalcError::Syntax { found: TokTp::Question, col: 9, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn assert_statement() {
+        assert_eq!(parse("assert a != 1").unwrap(), vec![Stmt::Assert {
+            cond: Cond { lhs: ident("a", 7), op: CompOp::Ne, rhs: lit(1, 12) },
+            span: at(1, 0, 13),
+        }]);
+        assert!(parse("do assert i < 3 i := i + 1 od; assert i == 3").is_ok());
+        assert!(parse("assert i").is_err());
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        assert_eq!(pretty_print(&parse("n++").unwrap()), "n := n + 1\n");
+        let stmts = parse("n--").unwrap();
+        assert_eq!(stmts, vec![Stmt::Assign { name: "n".to_string(),
+            expr: Expr::binary(BinOp::Sub, ident("n", 0),
+                Expr::ILit(1, at(1, 1, 3)), at(1, 1, 3)),
+            span: at(1, 0, 3) }]);
+        assert_eq!(parse("x++ y-- ; z := 1").unwrap().len(), 3);
+        // a - -b is a minus and a negation, as long as they're apart
+        assert!(parse("x := a - -b").is_ok());
+        assert!(parse("x := a--b").is_err());
+        assert!(parse("x := a++").is_err());
+        assert!(parse("++x").is_err());
+    }
+
+    #[test]
+    fn compound_assignment() {
+        for &(src, plain) in &[("x += 2", "x := x + 2"), ("x -= y", "x := x - y"),
+                ("x *= a + 1", "x := x * (a + 1)"), ("x /= 2 ** n", "x := x / 2 ** n")] {
+            assert_eq!(pretty_print(&parse(src).unwrap()), format!("{}\n", plain));
+        }
+        let stmts = parse("n -= 1").unwrap();
+        assert_eq!(stmts, vec![Stmt::Assign { name: "n".to_string(),
+            expr: Expr::binary(BinOp::Sub, ident("n", 0), lit(1, 5), at(1, 2, 4)),
+            span: at(1, 0, 6) }]);
+        // with a space, * = is a times and a stray gets
+        assert!(parse("x * = 2").is_err());
+        assert!(parse("x += ").is_err());
+        assert!(parse("check x += 1").is_err());
+    }
+
+    fn set(tps: &[TokTp]) -> HashSet<TokTp> {
+        tps.iter().copied().collect()
+    }
+
+    // The tokens the first syntax error in src says were expected.
+    fn expected(src: &str) -> HashSet<TokTp> {
+        match parse(src).unwrap_err() {
+            CalcError::Syntax { expected, .. } => set(&expected),
+            e => panic!("not a syntax error: {}", e),
+        }
+    }
+
+    // What a nonterminal's match arms accept: FIRST, plus FOLLOW if it
+    // can be empty.
+    fn predicts(nt: &str) -> HashSet<TokTp> {
+        let mut tps = grammar::first(nt);
+        if grammar::nullable(nt) {
+            tps.extend(grammar::follow(nt));
+        }
+        tps
+    }
+
+    #[test]
+    fn sets_agree_with_grammar() {
+        assert_eq!(set(FIRST_STMT), grammar::first("stmt"));
+        assert_eq!(set(FIRST_EXPR), grammar::first("expr"));
+        assert_eq!(set(FOLLOW_STMT), grammar::follow("stmt"));
+        assert_eq!(set(FOLLOW_EXPR), grammar::follow("expr"));
+        assert_eq!(set(COMP_OPS), grammar::first("comp_op"));
+        // the tails predict epsilon on FOLLOW_EXPR plus the looser operators
+        assert_eq!(predicts("bit_tail"), set(&[BIT_OPS, FOLLOW_EXPR].concat()));
+        assert_eq!(predicts("term_tail"),
+            set(&[&[TokTp::Plus, TokTp::Minus], BIT_OPS, FOLLOW_EXPR].concat()));
+        assert_eq!(predicts("factor_tail"), set(&[&[TokTp::Times, TokTp::DivBy, TokTp::Mod,
+            TokTp::Plus, TokTp::Minus], BIT_OPS, FOLLOW_EXPR].concat()));
+
+        // Each source goes wrong at its last token, in the named
+        // nonterminal, which should list everything its arms accept.
+        for &(src, nt) in &[(":=", "program"), ("if a < b )", "stmt_list"), ("read :=", "type"),
+                ("const :=", "type"), ("write :=", "write_arg"), ("check :=", "comp"),
+                ("x := :=", "expr"), ("x := a 1", "power_tail"), ("x := -:=", "factor"),
+                ("x := (a ;", "cond_tail"), ("check a )", "comp_op"),
+                ("int x )", "init_part")] {
+            // const needs a type, so doesn't accept what follows one
+            let want = if src == "const :=" { grammar::first(nt) } else { predicts(nt) };
+            assert_eq!(expected(src), want, "{} in {}", nt, src);
+        }
+    }
+
+    #[test]
+    fn pascal_not_equal() {
+        assert_eq!(parse("check a <> b"), parse("check a != b"));
+        assert!(parse("check a <> b").is_ok());
+    }
+
+    #[test]
+    fn comparisons_need_an_operator() {
+        let stmts = parse("check a == b").unwrap();
+        assert_eq!(stmts, vec![Stmt::Check {
+            cond: Cond { lhs: ident("a", 6), op: CompOp::Eq, rhs: ident("b", 11) },
+            span: at(1, 0, 12),
+        }]);
+        assert!(parse("check -trunc(r) < float(i) * 2").is_ok());
+        match parse("x := 1\ncheck a b") {
+            Err(CalcError::Syntax { found, line, col, index, .. }) => {
+                assert_eq!((found, line, col, index), (TokTp::Ident, 2, 8, 5));
             }
+            other => panic!("expected a syntax error, got {:?}", other),
         }
+    }
 
-        fn comp_op(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Greater => {
-                    println!("predict comp_op --> greater");
-                    self.eat(TokTp::Greater);
-                }
-                TokTp::Lesser => {
-                    println!("predict comp_op --> lesser");
-                    self.eat(TokTp::Lesser);
-                }
-                TokTp::EqualTo => {
-                    println!("predict comp_op --> equalto");
-                    self.eat(TokTp::EqualTo);
-                }
-                TokTp::NEqualTo => {
-                    println!("predict comp_op --> nequalto");
-                    self.eat(TokTp::NEqualTo);
-                }
-                TokTp::GreaterEq => {
-                    println!("predict comp_op --> greatereq");
-                    self.eat(TokTp::GreaterEq);
-                }
-                TokTp::LesserEq => {
-                    println!("predict comp_op --> lessereq");
-                    self.eat(TokTp::LesserEq);
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    #[test]
+    fn elsif_chains() {
+        // n < v, and write v, starting at col
+        let cond = |v, col| Cond { lhs: ident("n", col), op: CompOp::Lt, rhs: lit(v, col + 4) };
+        let write = |v, col| vec![Stmt::Write { args: vec![WriteArg::Expr(lit(v, col + 6))],
+            span: at(1, col, col + 7) }];
+        assert_eq!(parse("if n < 1 write 1 elsif n < 2 write 2 elsif n < 3 write 3 else write 4 fi")
+            .unwrap(), vec![Stmt::If {
+                branches: vec![(cond(1, 3), write(1, 9)), (cond(2, 23), write(2, 29)),
+                    (cond(3, 43), write(3, 49))],
+                else_body: Some(write(4, 62)),
+                span: at(1, 0, 72),
+            }]);
+        assert_eq!(parse("if n < 1 elsif n < 2 write 2 fi").unwrap(), vec![Stmt::If {
+            branches: vec![(cond(1, 3), vec![]), (cond(2, 15), write(2, 21))],
+            else_body: None,
+            span: at(1, 0, 31),
+        }]);
+        assert!(parse("if n < 1 else write 1 elsif n < 2 fi").is_err());
+        assert!(parse("elsif n < 1 fi").is_err());
+    }
+
+    #[test]
+    fn declarations_without_a_value() {
+        assert_eq!(parse("int n\nreal r").unwrap(), vec![
+            Stmt::Decl { tp: Type::Int, name: "n".to_string(), expr: None, span: at(1, 0, 5) },
+            Stmt::Decl { tp: Type::Real, name: "r".to_string(), expr: None, span: at(2, 0, 6) },
+        ]);
+        assert_eq!(trace("int n write n")[2..5], ["predict stmt --> int ident init_part",
+            "matched Int", "matched Ident: n"]);
+        assert_eq!(trace("int n write n")[5], "predict init_part --> epsilon");
+        // the value, or whatever may follow a statement
+        assert_eq!(expected("int n )"), predicts("init_part"));
+        assert!(expected("int n )").contains(&TokTp::Gets));
+    }
+
+    #[test]
+    fn constants_need_a_type() {
+        assert_eq!(parse("const real half := 0.5").unwrap(), vec![Stmt::Const {
+            tp: Type::Real, name: "half".to_string(), expr: Expr::RLit(0.5, at(1, 19, 22)),
+            span: at(1, 0, 22) }]);
+        match parse("const pi := 3") {
+            Err(CalcError::Syntax { expected, found, .. }) => {
+                assert_eq!(expected, vec![TokTp::Int, TokTp::Real]);
+                assert_eq!(found, TokTp::Ident);
             }
+            other => panic!("expected a syntax error, got {:?}", other),
         }
+    }
 
-        fn add_op(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Plus => {
-                    println!("predict add_op --> plus");
-                    self.eat(TokTp::Plus);
-                }
-                TokTp::Minus => {
-                    println!("predict add_op --> minus");
-                    self.eat(TokTp::Minus);
-                }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+    #[test]
+    fn semicolons_are_optional_separators() {
+        // name := v, starting at col
+        let assign = |name: &str, v, col| Stmt::Assign { name: name.to_string(),
+            expr: lit(v, col + 5), span: at(1, col, col + 6) };
+        assert_eq!(parse("x := 1; y := 2").unwrap(), vec![assign("x", 1, 0), assign("y", 2, 8)]);
+        assert_eq!(parse("; x := 1;; y := 2;").unwrap(),
+            vec![assign("x", 1, 2), assign("y", 2, 11)]);
+        assert_eq!(parse(";").unwrap(), vec![]);
+        assert!(parse("do check x < 3; x := x + 1; od; write x;").is_ok());
+        assert!(parse("read int a, b; write a; write \"s\", b;").is_ok());
+        // a semicolon is also where recovery can pick up again
+        let mut p = parser("x := ; y := 2; z := (1");
+        assert!(p.parse().is_err());
+        assert_eq!(p.errors().len(), 2);
+    }
+
+    #[test]
+    fn braces_group_statements() {
+        // the same statements, if not at the same columns
+        let same = |a: &str, b: &str| {
+            pretty_print(&parse(a).unwrap()) == pretty_print(&parse(b).unwrap())
+        };
+        assert!(same("if a < b { x := 1 y := 2 } fi", "if a < b x := 1 y := 2 fi"));
+        assert!(same("do { check i < 3 } { i := i + 1 } od", "do check i < 3 i := i + 1 od"));
+        assert!(parse("if a < b { } elsif a > b {x := 1;} else { {x := 2} } fi").is_ok());
+        assert!(parse("for i := 1 to 3 do { write i } od { write 0 }").is_ok());
+        match parse("if a < b {\n  x := 1\nfi") {
+            Err(CalcError::Unmatched { open: TokTp::LBrace, open_line: 1, found: TokTp::Fi,
+                line: 3, col: 0 }) => {}
+            other => panic!("{:?}", other),
+        }
+        match parse("do\n{ x := 1\n{ y := 2 }\nod") {
+            Err(CalcError::Unmatched { open_line: 2, found: TokTp::Od, line: 4, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+        match parse("if a < b\n  x := 1 }\nfi") {
+            Err(CalcError::Syntax { found: TokTp::RBrace, line: 2, .. }) => {}
+            other => panic!("{:?}", other),
+        }
+        assert!(parse("x := 1 }").is_err());
+    }
+
+    #[test]
+    fn read_type_is_optional() {
+        let read = |tp, len| Stmt::Read { tp, names: vec!["x".to_string()], span: at(1, 0, len) };
+        assert_eq!(parse("read int x").unwrap(), vec![read(Some(Type::Int), 10)]);
+        assert_eq!(parse("read real x").unwrap(), vec![read(Some(Type::Real), 11)]);
+        assert_eq!(parse("read x").unwrap(), vec![read(None, 6)]);
+        match parse("read") {
+            Err(CalcError::Syntax { expected, found, .. }) => {
+                assert_eq!(expected, vec![TokTp::Int, TokTp::Real, TokTp::Ident]);
+                assert_eq!(found, TokTp::End);
             }
+            other => panic!("expected a syntax error, got {:?}", other),
         }
+    }
 
-        
+    // A Write sink the test can still read after handing it to a parser.
+    #[derive(Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
 
-        fn mul_op(&mut self) {
-            match self.next_tok.tp {
-                TokTp::Times => {
-                    println!("predict mul_op --> times");
-                    self.eat(TokTp::Times);
-                }
-                TokTp::DivBy => {
-                    println!("predict mul_op --> div_by");
-                    self.eat(TokTp::DivBy);
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_goes_to_output() {
+        let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+        let scanner = Scanner::from_input(Input::from_str("write x"));
+        let mut p = Parser::with_output(scanner, Box::new(buf.clone()));
+        p.parse().unwrap();
+        let trace = String::from_utf8(buf.0.borrow().clone()).unwrap();
+        assert_eq!(trace, "\
+predict program --> stmt_list $$
+predict stmt_list --> stmt stmt_list
+predict stmt --> write write_arg write_arg_tail
+matched Write
+predict write_arg --> expr
+predict expr --> sum bit_tail
+predict sum --> term term_tail
+predict term --> power factor_tail
+predict power --> factor power_tail
+predict factor --> ident
+matched Ident: x
+predict power_tail --> epsilon
+predict factor_tail --> epsilon
+predict term_tail --> epsilon
+predict bit_tail --> epsilon
+predict write_arg_tail --> epsilon
+predict stmt_list --> epsilon
+matched End
+");
+
+        let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+        let scanner = Scanner::from_input(Input::from_str("write x"));
+        let mut p = Parser::with_output(scanner, Box::new(buf.clone()));
+        p.set_trace(false);
+        p.parse().unwrap();
+        assert!(buf.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn trace_shows_positions() {
+        let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+        let scanner = Scanner::from_input(Input::from_str("x := 1\n  write x"));
+        let mut p = Parser::with_output(scanner, Box::new(buf.clone()));
+        p.set_trace_pos(true);
+        p.parse().unwrap();
+        let trace = String::from_utf8(buf.0.borrow().clone()).unwrap();
+        assert_eq!(trace, "\
+[1:0] predict program --> stmt_list $$
+[1:0] predict stmt_list --> stmt stmt_list
+[1:0] predict stmt --> ident gets assign_value
+[1:0] matched Ident: x
+[1:2] matched Gets
+[1:5] predict assign_value --> expr
+[1:5] predict expr --> sum bit_tail
+[1:5] predict sum --> term term_tail
+[1:5] predict term --> power factor_tail
+[1:5] predict power --> factor power_tail
+[1:5] predict factor --> i_lit
+[1:5] matched ILit: 1
+[2:2] predict power_tail --> epsilon
+[2:2] predict factor_tail --> epsilon
+[2:2] predict term_tail --> epsilon
+[2:2] predict bit_tail --> epsilon
+[2:2] predict stmt_list --> stmt stmt_list
+[2:2] predict stmt --> write write_arg write_arg_tail
+[2:2] matched Write
+[2:8] predict write_arg --> expr
+[2:8] predict expr --> sum bit_tail
+[2:8] predict sum --> term term_tail
+[2:8] predict term --> power factor_tail
+[2:8] predict power --> factor power_tail
+[2:8] predict factor --> ident
+[2:8] matched Ident: x
+[3:0] predict power_tail --> epsilon
+[3:0] predict factor_tail --> epsilon
+[3:0] predict term_tail --> epsilon
+[3:0] predict bit_tail --> epsilon
+[3:0] predict write_arg_tail --> epsilon
+[3:0] predict stmt_list --> epsilon
+[3:0] matched End
+");
+    }
+
+    #[test]
+    fn trace_events_go_to_sink() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = events.clone();
+        let mut p = Parser::with_trace_sink(Scanner::from_str("x := 1"),
+            move |event| sink.borrow_mut().push(event));
+        p.parse().unwrap();
+        let events = events.borrow();
+        let matched: Vec<&TraceEvent> = events.iter()
+            .filter(|e| matches!(**e, TraceEvent::Match { .. })).collect();
+        let matched_tok = |tp, text: &str| TraceEvent::Match { tp, text: text.to_string() };
+        assert_eq!(matched, vec![&matched_tok(TokTp::Ident, "x"), &matched_tok(TokTp::Gets, ":="),
+            &matched_tok(TokTp::ILit, "1"), &matched_tok(TokTp::End, "")]);
+        assert_eq!(events[2], TraceEvent::Predict("stmt --> ident gets assign_value".to_string()));
+        assert_eq!(events[2].to_string(), "predict stmt --> ident gets assign_value");
+        assert_eq!(events[3].to_string(), "matched Ident: x");
+        assert_eq!(events[4].to_string(), "matched Gets");
+    }
+
+    // The printed trace of parsing src.
+    fn trace(src: &str) -> Vec<String> {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let sink = lines.clone();
+        let mut p = Parser::with_trace_sink(Scanner::from_str(src),
+            move |event| sink.borrow_mut().push(event.to_string()));
+        p.parse().unwrap();
+        let lines = lines.borrow().clone();
+        lines
+    }
+
+    #[test]
+    fn mod_keyword_is_percent() {
+        assert_eq!(trace("x := a mod b"), trace("x := a % b"));
+        assert!(trace("x := a mod b").contains(&"predict mul_op --> mod".to_string()));
+        assert_eq!(pretty_print(&parse("x := a mod b mod 2").unwrap()), "x := a % b % 2\n");
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(parse("write 6 & 3").unwrap(), vec![Stmt::Write {
+            args: vec![WriteArg::Expr(Expr::binary(BinOp::BitAnd, lit(6, 6), lit(3, 10),
+                at(1, 8, 9)))],
+            span: at(1, 0, 11) }]);
+        assert!(trace("write 6 & 3").contains(&"predict bit_tail --> bit_op sum bit_tail"
+            .to_string()));
+        // looser than arithmetic, left-associative among themselves
+        assert_eq!(pretty_print(&parse("x := a & b + 1 | c ^ d * 2").unwrap()),
+            "x := a & b + 1 | c ^ d * 2\n");
+        assert_eq!(pretty_print(&parse("x := (a | b) & c").unwrap()), "x := a | b & c\n");
+        assert_eq!(pretty_print(&parse("x := a | (b & c)").unwrap()), "x := a | (b & c)\n");
+        // but tighter than a comparison
+        assert!(parse("check n & 1 == 1").is_ok());
+        assert_eq!(expected("x := a & )"), set(FIRST_EXPR));
+    }
+
+    #[test]
+    fn print_and_input_are_write_and_read() {
+        assert_eq!(parse("print 5"), parse("write 5"));
+        assert_eq!(pretty_print(&parse("input int x, y print x + y, \"!\"").unwrap()),
+            "read int x, y\nwrite x + y, \"!\"\n");
+        // but only as whole words
+        assert_eq!(pretty_print(&parse("printing := 1").unwrap()), "printing := 1\n");
+        assert!(trace("print 5").contains(&"matched Write".to_string()));
+    }
+
+    #[test]
+    fn end_of_input_is_after_the_last_line() {
+        for (src, line) in &[("if a < b", 2), ("x := 1\nif a < b\n", 3), ("if a < b\n\n", 3),
+                ("if a < b\n// done", 3), ("if a < b /* ...\n */", 3), ("if a < b \\\n", 2)] {
+            match parse(src) {
+                Err(CalcError::Syntax { found: TokTp::End, line: l, col: 0, .. }) =>
+                    assert_eq!(l, *line, "{:?}", src),
+                other => panic!("{:?}: {:?}", src, other),
+            }
+        }
+    }
+
+    #[test]
+    fn empty_programs() {
+        for src in &["", "  \n\t\n", "// nothing\n/* at\n all */\n"] {
+            let buf = SharedBuf(Rc::new(RefCell::new(Vec::new())));
+            let scanner = Scanner::from_input(Input::from_str(src));
+            let mut p = Parser::with_output(scanner, Box::new(buf.clone()));
+            p.build_parse_tree();
+            assert_eq!(p.parse().unwrap(), vec![]);
+            assert!(p.errors().is_empty());
+            let trace = String::from_utf8(buf.0.borrow().clone()).unwrap();
+            assert_eq!(trace, "\
+predict program --> stmt_list $$
+predict stmt_list --> epsilon
+matched End
+");
+            assert!(!p.parse_tree().unwrap().to_dot().contains("Begin"));
+        }
+    }
+
+    #[test]
+    fn nodes_span_their_tokens() {
+        let stmts = parse("x := -(a + 1) * trunc(r)\nif a < b\n  write \"s\" fi").unwrap();
+        let expr = match stmts[0] {
+            Stmt::Assign { ref expr, span, .. } => {
+                assert_eq!(span, at(1, 0, 24));
+                expr
+            }
+            ref other => panic!("{:?}", other),
+        };
+        assert_eq!(expr.span(), at(1, 5, 24));
+        match *expr {
+            Expr::Binary { ref lhs, ref rhs, op_span, .. } => {
+                assert_eq!(op_span, at(1, 14, 15));
+                // the parentheses are part of the negation, not the sum
+                assert_eq!(lhs.span(), at(1, 5, 13));
+                match **lhs {
+                    Expr::Neg { ref operand, .. } => assert_eq!(operand.span(), at(1, 7, 12)),
+                    ref other => panic!("{:?}", other),
                 }
-                _ => panic!("syntax error on line {}", self.next_tok.line),
+                assert_eq!(rhs.span(), at(1, 16, 24));
             }
+            ref other => panic!("{:?}", other),
         }
+        // a statement can span lines
+        assert_eq!(stmts[1].span(), Span::new(2, 0, 3, 14));
+        match stmts[1] {
+            Stmt::If { ref branches, .. } => assert_eq!(branches[0].0.span(), at(2, 3, 8)),
+            ref other => panic!("{:?}", other),
+        }
+    }
 
-    } // end impl Parser
-// HOW DO WE ADD THE I_LIT/R_LIT PRODUCTION?
-} // end mod parser
+    #[test]
+    fn recovers_from_syntax_errors() {
+        assert!(parse("x := * 3\nwrite 4\ny := (1 +\nread z").is_err());
+        assert!(parse(")) write 1").is_err());
+    }
+
+    #[test]
+    fn collects_independent_errors() {
+        let mut p = parser("x := * 3\nwrite 4\ny := 2 +\nwrite y");
+        assert!(p.parse().is_err());
+        let lines: Vec<usize> = p.errors().iter().map(|e| e.line()).collect();
+        assert_eq!(lines, vec![1, 4]);
+        assert_eq!(p.errors()[0].col(), Some(5));
+        assert!(parse("x := 2 + 3").is_ok());
+    }
+
+    #[test]
+    fn counts_the_derivation() {
+        let mut p = parser("x := 7");
+        p.parse().unwrap();
+        // program stmt_list stmt assign_value expr sum term power factor
+        // power_tail factor_tail term_tail bit_tail stmt_list; x := 7 $$
+        assert_eq!(p.stats(), ParseStats { productions_predicted: 14, tokens_matched: 4,
+            max_depth: 9 });
+        // nesting parentheses deepens the tree by expr sum term power factor
+        let mut p = parser("write (1)");
+        p.parse().unwrap();
+        assert_eq!(p.stats().max_depth, 14);
+        // a later, shallower statement doesn't lower the maximum
+        let mut p = parser("write ((1)) x := 1");
+        p.parse().unwrap();
+        assert_eq!(p.stats().max_depth, 19);
+        assert_eq!(p.stats().tokens_matched, 10);
+    }
+
+    #[test]
+    fn position_follows_the_tokens() {
+        let mut p = parser("x := 1\n  write x");
+        assert_eq!((p.current_line(), p.current_col()), (0, 0));
+        p.advance();
+        let mut positions = vec![(p.current_line(), p.current_col())];
+        for &tp in &[TokTp::Ident, TokTp::Gets, TokTp::ILit, TokTp::Write] {
+            p.eat(tp).unwrap();
+            positions.push((p.current_line(), p.current_col()));
+        }
+        assert_eq!(positions, vec![(1, 0), (1, 2), (1, 5), (2, 2), (2, 8)]);
+        let mut p = parser("x := 1\n  write x");
+        p.parse().unwrap();
+        assert_eq!((p.current_line(), p.current_col()), (3, 0));
+    }
 
-use crate::parser::Parser;
+    #[test]
+    fn stops_after_max_errors() {
+        // one error a line, each recovered from
+        let garbled = "x := * 3\n".repeat(30) + "write 1 @";
+        let mut p = parser(&garbled);
+        p.set_max_errors(5);
+        assert!(p.parse().is_err());
+        assert_eq!(p.errors().len(), 5);
+        assert_eq!(p.errors()[4].line(), 5);
+        assert!(p.gave_up());
+        // by default, up to 20; 0 is no limit
+        let mut p = parser(&garbled);
+        assert!(p.parse().is_err());
+        assert_eq!((p.errors().len(), p.gave_up()), (DEFAULT_MAX_ERRORS, true));
+        let mut p = parser(&garbled);
+        p.set_max_errors(0);
+        assert!(p.parse().is_err());
+        assert_eq!((p.errors().len(), p.gave_up()), (31, false));
+        // lexical errors count too
+        let mut p = parser("x := 1 @ # y := 2 $ z := *");
+        p.set_max_errors(2);
+        assert!(p.parse().is_err());
+        assert_eq!((p.errors().len(), p.gave_up()), (2, true));
+    }
 
-fn main() {
-    let mut parser = Parser::new();
-    parser.parse();
+    #[test]
+    fn ignores_comment_tokens() {
+        let mut scanner = Scanner::from_input(Input::from_str("x := /* 1 */ 2 // three"));
+        scanner.set_keep_comments(true);
+        let mut p = Parser::from_scanner(scanner);
+        p.set_trace(false);
+        let stmts = p.parse().unwrap();
+        assert_eq!(stmts, vec![Stmt::Assign { name: "x".to_string(), expr: lit(2, 13),
+            span: at(1, 0, 14) }]);
+    }
+
+    #[test]
+    fn records_lexical_errors() {
+        let mut p = parser("x := @1\nwrite 2");
+        assert!(p.parse().is_err());
+        assert_eq!(p.errors(), &[CalcError::Lex {
+            msg: "unexpected character '@' (0x40)".to_string(), line: 1, col: 5 }]);
+    }
 }
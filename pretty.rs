@@ -0,0 +1,221 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Pretty-printer
+//
+//  Turns an AST back into source text in a canonical layout: one
+//  statement per line, two-space indentation inside blocks, single spaces
+//  around binary operators, and only the parentheses that precedence
+//  and left-associativity require.
+//
+
+use crate::ast::*;
+use crate::eval::{format_value, Value};
+
+pub fn pretty_print(stmts: &[Stmt]) -> String {
+    let mut out = String::new();
+    stmt_list(stmts, 0, &mut out);
+    out
+}
+
+fn stmt_list(stmts: &[Stmt], depth: usize, out: &mut String) {
+    for stmt in stmts {
+        self::stmt(stmt, depth, out);
+    }
+}
+
+fn line(depth: usize, text: &str, out: &mut String) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+    out.push_str(text);
+    out.push('\n');
+}
+
+fn stmt(stmt: &Stmt, depth: usize, out: &mut String) {
+    match *stmt {
+        Stmt::Assign { ref name, ref expr, .. } => {
+            line(depth, &format!("{} := {}", name, self::expr(expr)), out);
+        }
+        Stmt::Decl { tp, ref name, expr: Some(ref expr), .. } => {
+            line(depth, &format!("{} {} := {}", tp, name, self::expr(expr)), out);
+        }
+        Stmt::Decl { tp, ref name, expr: None, .. } => {
+            line(depth, &format!("{} {}", tp, name), out);
+        }
+        Stmt::Const { tp, ref name, ref expr, .. } => {
+            line(depth, &format!("const {} {} := {}", tp, name, self::expr(expr)), out);
+        }
+        Stmt::Read { tp, ref names, .. } => {
+            let tp = tp.map_or(String::new(), |tp| format!("{} ", tp));
+            line(depth, &format!("read {}{}", tp, names.join(", ")), out);
+        }
+        Stmt::Write { ref args, .. } => {
+            let args: Vec<String> = args.iter().map(|arg| match *arg {
+                WriteArg::Expr(ref expr) => self::expr(expr),
+                WriteArg::Str(ref s, _) => string_literal(s),
+            }).collect();
+            line(depth, &format!("write {}", args.join(", ")), out);
+        }
+        Stmt::If { ref branches, ref else_body, .. } => {
+            for (i, (cond, body)) in branches.iter().enumerate() {
+                let keyword = if i == 0 { "if" } else { "elsif" };
+                line(depth, &format!("{} {}", keyword, self::cond(cond)), out);
+                stmt_list(body, depth + 1, out);
+            }
+            if let Some(ref else_body) = *else_body {
+                line(depth, "else", out);
+                stmt_list(else_body, depth + 1, out);
+            }
+            line(depth, "fi", out);
+        }
+        Stmt::Do { ref body, .. } => {
+            line(depth, "do", out);
+            stmt_list(body, depth + 1, out);
+            line(depth, "od", out);
+        }
+        Stmt::For { ref var, ref from, ref to, ref body, .. } => {
+            line(depth, &format!("for {} := {} to {} do", var, expr(from), expr(to)), out);
+            stmt_list(body, depth + 1, out);
+            line(depth, "od", out);
+        }
+        Stmt::Check { ref cond, .. } => line(depth, &format!("check {}", self::cond(cond)), out),
+        Stmt::Assert { ref cond, .. } => line(depth, &format!("assert {}", self::cond(cond)), out),
+    }
+}
+
+fn cond(cond: &Cond) -> String {
+    let op = match cond.op {
+        CompOp::Eq => "==",
+        CompOp::Ne => "!=",
+        CompOp::Lt => "<",
+        CompOp::Gt => ">",
+        CompOp::Le => "<=",
+        CompOp::Ge => ">=",
+    };
+    format!("{} {} {}", expr(&cond.lhs), op, expr(&cond.rhs))
+}
+
+// Binding strength: assignments, bitwise operators, terms, factors,
+// powers, then everything that needs no help.
+const ASSIGN: u8 = 0;
+const BITWISE: u8 = 1;
+const SUM: u8 = 2;
+const PRODUCT: u8 = 3;
+const POWER: u8 = 4;
+const ATOM: u8 = 5;
+
+fn precedence(expr: &Expr) -> u8 {
+    match *expr {
+        Expr::Binary { op: BinOp::Add, .. } | Expr::Binary { op: BinOp::Sub, .. } => SUM,
+        Expr::Binary { op: BinOp::Pow, .. } => POWER,
+        Expr::Binary { op, .. } if op.is_bitwise() => BITWISE,
+        Expr::Binary { .. } => PRODUCT,
+        Expr::Assign { .. } => ASSIGN,
+        _ => ATOM,
+    }
+}
+
+// expr, parenthesized if it binds less tightly than min.
+fn operand(expr: &Expr, min: u8) -> String {
+    if precedence(expr) < min {
+        format!("({})", self::expr(expr))
+    } else {
+        self::expr(expr)
+    }
+}
+
+fn expr(expr: &Expr) -> String {
+    match *expr {
+        Expr::Ident { ref name, .. } => name.clone(),
+        Expr::ILit(i, _) => i.to_string(),
+        // keeps the '.', or it would read back as an int
+        Expr::RLit(r, _) => format_value(&Value::Real(r)),
+        // "--x" would be a decrement
+        Expr::Neg { ref operand, .. } => match **operand {
+            Expr::Neg { .. } => format!("-({})", self::expr(operand)),
+            _ => format!("-{}", self::operand(operand, ATOM)),
+        },
+        Expr::Trunc(ref operand, _) => format!("trunc({})", self::expr(operand)),
+        Expr::Float(ref operand, _) => format!("float({})", self::expr(operand)),
+        Expr::Binary { op: BinOp::Pow, ref lhs, ref rhs, .. } => {
+            // right-associative, so it's the left operand that needs help
+            format!("{} ** {}", operand(lhs, POWER + 1), operand(rhs, POWER))
+        }
+        Expr::Binary { op, ref lhs, ref rhs, .. } => {
+            let (text, prec) = match op {
+                BinOp::Add => ("+", SUM),
+                BinOp::Sub => ("-", SUM),
+                BinOp::Mul => ("*", PRODUCT),
+                BinOp::Div => ("/", PRODUCT),
+                BinOp::Mod => ("%", PRODUCT),
+                BinOp::BitAnd => ("&", BITWISE),
+                BinOp::BitOr => ("|", BITWISE),
+                BinOp::BitXor => ("^", BITWISE),
+                BinOp::Pow => unreachable!(),
+            };
+            // operators are left-associative, so a right operand at the
+            // same level needs parentheses
+            format!("{} {} {}", operand(lhs, prec), text, operand(rhs, prec + 1))
+        }
+        Expr::Assign { ref name, ref value, .. } => format!("{} := {}", name, self::expr(value)),
+        Expr::Ternary { ref cond, ref then, ref otherwise, .. } => {
+            format!("({} ? {} : {})", self::cond(cond), self::expr(then), self::expr(otherwise))
+        }
+    }
+}
+
+fn string_literal(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse_src as parse;
+
+    #[test]
+    fn minimal_parentheses() {
+        assert_eq!(pretty_print(&parse("x:=(a*b)+(c%d)")), "x := a * b + c % d\n");
+        assert_eq!(pretty_print(&parse("x:=(a+b)*(c-d)")), "x := (a + b) * (c - d)\n");
+        assert_eq!(pretty_print(&parse("x:=(a-b)-c")), "x := a - b - c\n");
+        assert_eq!(pretty_print(&parse("x:=a-(b-c)")), "x := a - (b - c)\n");
+        assert_eq!(pretty_print(&parse("x:=a/(b*c)")), "x := a / (b * c)\n");
+        assert_eq!(pretty_print(&parse("x:=-(a+b)*- -c")), "x := -(a + b) * -(-c)\n");
+        assert_eq!(pretty_print(&parse("x:=(a**b)**(c**d)")), "x := (a ** b) ** c ** d\n");
+        assert_eq!(pretty_print(&parse("x:=-(a**2)*(-a)**2")), "x := -(a ** 2) * -a ** 2\n");
+        assert_eq!(pretty_print(&parse("read int a,b write a*(b),\"x\"")),
+            "read int a, b\nwrite a * b, \"x\"\n");
+        assert_eq!(pretty_print(&parse("write trunc((2.0))+float(3)")),
+            "write trunc(2.0) + float(3)\n");
+        assert_eq!(pretty_print(&parse("x:=y:=(z)+1")), "x := y := z + 1\n");
+        assert_eq!(pretty_print(&parse("x:=(a<b?(a):b+1)*2")), "x := (a < b ? a : b + 1) * 2\n");
+    }
+
+    #[test]
+    fn reparses_to_same_layout() {
+        let src = "read int n real r := float(n) / 2.0 int m write \"n/2 = \\\"\" write r";
+        let once = pretty_print(&parse(src));
+        assert_eq!(once,
+            "read int n\nreal r := float(n) / 2.0\nint m\nwrite \"n/2 = \\\"\"\nwrite r\n");
+        assert_eq!(pretty_print(&parse(&once)), once);
+    }
+
+    #[test]
+    fn indents_blocks() {
+        let stmts = parse("do check n < 3 if n == 1 write n else read n fi od");
+        assert_eq!(pretty_print(&stmts),
+            "do\n  check n < 3\n  if n == 1\n    write n\n  else\n    read n\n  fi\nod\n");
+        let src = "if n==1 write 1 elsif n==2 write 2 fi";
+        assert_eq!(pretty_print(&parse(src)),
+            "if n == 1\n  write 1\nelsif n == 2\n  write 2\nfi\n");
+    }
+}
@@ -0,0 +1,1101 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Scanner
+//
+//  Literals are strings of ASCII digits.  With allow_underscores, digits
+//  may be grouped with single underscores (1_000_000), which are dropped
+//  from the token's text.
+//  Identifiers are strings of Unicode alphabetics.  With the optional
+//  unicode-normalization feature they may also contain combining marks,
+//  and are normalized to NFC, so a name means the same thing whether its
+//  accents were typed precomposed or not.  Only identifiers (and so
+//  keywords) are normalized; string and character literals are kept
+//  exactly as written.
+//
+//  White space characters are tossed (no tokens contain such characters).
+//  Since line feeds are white space, no token spans a line boundary --
+//  except a /* */ comment, which is only a token with keep_comments.
+//  Comments (// to end of line, or /* to */) are otherwise tossed too.
+//
+
+use crate::input::Input;
+use crate::input::SourceChar;
+use crate::input::EOF;
+use crate::error::CalcError;
+use crate::span::Span;
+use std::fmt;
+use std::io::BufRead;
+
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+    // allow enum values to be compared for equality, (debug) printed, and copied
+pub enum TokTp {Begin, Read, Write, Ident, ILit, RLit, Gets, Greater, Lesser, EqualTo, NEqualTo, GreaterEq, LesserEq,
+    If, Elsif, Else, Fi, Do, Od, Check, Assert, For, To, Const, Int, Real, Trunc, Float, Plus, Minus, Incr, Decr, Times, Pow, DivBy, Mod, PlusEq, MinusEq, TimesEq, DivEq, BitAnd, BitOr, BitXor, LParen, RParen, LBrace, RBrace, Comma, Semi, Question, Colon, SLit, CLit, Comment, End} //do we need to add i_lit and r_lit or is literal good enough?
+    // Begin is a dummy value with which to prime the constructor.
+// The variant's name, as for Debug, but honoring width and alignment,
+// so tokens can be printed in columns.
+impl fmt::Display for TokTp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(&format!("{:?}", self))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub tp: TokTp,
+    pub text: String,
+    pub span: Span,
+    pub index: usize,       // tokens the scanner returned before this one
+}
+
+impl Token {
+    // One-line JSON object, e.g. {"tp":"Ident","text":"x","line":1,"col":0}
+    pub fn to_json(&self) -> String {
+        format!("{{\"tp\":\"{:?}\",\"text\":{},\"line\":{},\"col\":{}}}",
+            self.tp, json_string(&self.text), self.span.start_line, self.span.start_col)
+    }
+}
+
+// Quote s as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Lexing options.  The defaults are the language as specified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannerConfig {
+    pub case_insensitive: bool,     // match keywords regardless of case
+    pub tab_width: usize,           // columns between tab stops; 0 is taken as 1
+    pub keep_comments: bool,        // return comments as tokens
+    pub allow_underscores: bool,    // digit separators in numbers
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        Self { case_insensitive: false, tab_width: 8, keep_comments: false,
+            allow_underscores: false }
+    }
+}
+
+pub struct Scanner {
+    input: Input,
+    next_char: SourceChar,      // already peeked at
+    done: bool,                 // iterator has returned None
+    peeked: Option<Result<Token, CalcError>>,   // scanned by peek(), not yet returned
+    config: ScannerConfig,
+    count: usize,               // tokens returned so far
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::with_config(ScannerConfig::default())
+    }
+
+    // Like new(), but treats IF, If, and if alike.  Identifier text
+    // keeps its original spelling either way.
+    pub fn new_case_insensitive() -> Self {
+        Self::with_config(ScannerConfig { case_insensitive: true, ..ScannerConfig::default() })
+    }
+
+    // Scan stdin with the given options.
+    pub fn with_config(config: ScannerConfig) -> Self {
+        Self::from_input_with_config(Input::new(), config)
+    }
+
+    // Scan input with the default options, keeping its tab width.
+    pub fn from_input(input: Input) -> Self {
+        let config = ScannerConfig { tab_width: input.tab_width(), ..ScannerConfig::default() };
+        Self::from_input_with_config(input, config)
+    }
+
+    pub fn from_input_with_config(mut input: Input, config: ScannerConfig) -> Self {
+        let config = ScannerConfig { tab_width: config.tab_width.max(1), ..config };
+        input.set_tab_width(config.tab_width);
+        Self {
+            input,
+            next_char: SourceChar { ch:' ', line: 0, col: 0 },
+            done: false,
+            peeked: None,
+            config,
+            count: 0,
+        }
+    }
+
+    // Scan from any buffered source; see Input::from_reader().
+    pub fn from_reader(reader: impl BufRead + 'static) -> Self {
+        Self::from_input(Input::from_reader(reader))
+    }
+
+    /// Scans an in-memory string, as if it had arrived on stdin.
+    ///
+    /// ```
+    /// use calc::{Scanner, TokTp};
+    ///
+    /// let mut scanner = Scanner::from_str("write x * 2");
+    /// let mut tps = Vec::new();
+    /// loop {
+    ///     let tok = scanner.scan().unwrap();
+    ///     if tok.tp == TokTp::End { break; }
+    ///     tps.push(tok.tp);
+    /// }
+    /// assert_eq!(tps, [TokTp::Write, TokTp::Ident, TokTp::Times, TokTp::ILit]);
+    /// ```
+    pub fn from_str(src: &str) -> Self {
+        Self::from_input(Input::from_str(src))
+    }
+
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    // Return comments as Comment tokens (text includes the delimiters)
+    // instead of skipping them.
+    pub fn set_keep_comments(&mut self, keep: bool) {
+        self.config.keep_comments = keep;
+    }
+
+    pub fn config(&self) -> &ScannerConfig {
+        &self.config
+    }
+
+    // The remaining tokens, as an iterator; see Tokens.
+    pub fn tokens(&mut self) -> Tokens<'_> {
+        Tokens { scanner: self }
+    }
+
+    // scan, like Token::getc, is a lot like Iterator::next(), but it doesn't
+    // return an Option.  Instead, it returns a sentinel (TokTp:End)
+    // at end of file.  This relieves the parser of the need to call
+    // next().unwrap_or(Token{ End, _, _, _ })
+    // Lexical errors come back as Err; the offending character(s) have
+    // already been consumed, so the caller may simply scan again.
+    // Successful scans are numbered from 0 in Token::index; errors and
+    // skipped comments don't count.
+    pub fn scan(&mut self) -> Result<Token, CalcError> {
+        if let Some(result) = self.peeked.take() {
+            return result;
+        }
+        let result = self.scan_token();
+        if result.is_ok() {
+            self.count += 1;
+        }
+        result
+    }
+
+    // What the next scan() will return, without consuming it: the token,
+    // or the lexical error in its way.  The input has moved past it, so
+    // input().current_line() may already be a later line.
+    pub fn peek(&mut self) -> Result<&Token, &CalcError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.scan());
+        }
+        self.peeked.as_ref().expect("just scanned").as_ref()
+    }
+
+    fn scan_token(&mut self) -> Result<Token, CalcError> {
+        let mut text = String::new();
+        loop {
+            while self.next_char.ch.is_whitespace() {
+                self.next_char = self.input.getc();
+            }
+            if self.next_char.ch != '/' { break; }
+            let second = self.input.peek2();
+            if second != '/' && second != '*' { break; }
+            let comment = self.comment()?;
+            if self.config.keep_comments {
+                return Ok(comment);
+            }
+        }
+        let col = self.next_char.col;
+        let line = self.next_char.line;
+        if self.next_char.ch == EOF {
+            // the empty line after the last, whatever path led here
+            let (line, _) = self.input.current_line();
+            return Ok(Token { tp: TokTp::End, text, span: Span::new(line, 0, line, 0),
+                index: self.count });
+        }
+        if self.next_char.ch.is_alphabetic() {
+            loop {
+                text.push(self.next_char.ch);
+                self.next_char = self.input.getc();
+                if !(self.next_char.ch == '_' ||
+                     self.next_char.ch.is_alphanumeric() ||
+                     is_combining_mark(self.next_char.ch)) { break; }
+            }
+            let text = nfc(text);
+            let word = if self.config.case_insensitive {
+                text.to_lowercase()
+            } else {
+                text.clone()
+            };
+            let tp = match word.as_str() {
+                "read"  => TokTp::Read,
+                "write" => TokTp::Write,
+                // synonyms, for beginners; the token keeps its spelling
+                "input" => TokTp::Read,
+                "print" => TokTp::Write,
+                "if"    => TokTp::If,
+                "elsif" => TokTp::Elsif,
+                "else"  => TokTp::Else,
+                "fi"    => TokTp::Fi,
+                "do"    => TokTp::Do,
+                "od"    => TokTp::Od,
+                "int"   => TokTp::Int,
+                "real"  => TokTp::Real,
+                "trunc" => TokTp::Trunc,
+                "float" => TokTp::Float,
+                "check" => TokTp::Check,
+                "assert" => TokTp::Assert,
+                "for"   => TokTp::For,
+                "to"    => TokTp::To,
+                "const" => TokTp::Const,
+                "mod"   => TokTp::Mod,      // a synonym for %
+                _       => TokTp::Ident,
+            };
+            return Ok(self.token(tp, text, line, col));
+        }
+        // i_lit is a string of digits; r_lit additionally has a single
+        // '.' that must be followed by at least one digit.
+        if self.next_char.ch.is_ascii_digit() {
+            let mut tp = TokTp::ILit;
+            loop {
+                text.push(self.next_char.ch);
+                self.next_char = self.input.getc();
+                if self.next_char.ch == '_' && self.config.allow_underscores {
+                    self.next_char = self.input.getc();
+                    if !self.next_char.ch.is_ascii_digit() {
+                        return Err(CalcError::Lex {
+                            msg: format!("malformed number '{}_': \
+                                expected digit after '_'", text),
+                            line, col });
+                    }
+                    continue;
+                }
+                if self.next_char.ch == '.' {
+                    text.push('.');
+                    self.next_char = self.input.getc();
+                    if tp == TokTp::RLit {
+                        return Err(CalcError::Lex {
+                            msg: format!("malformed number '{}'", text),
+                            line, col });
+                    }
+                    if !self.next_char.ch.is_ascii_digit() {
+                        return Err(CalcError::Lex {
+                            msg: format!("malformed number '{}': \
+                                expected digit after '.'", text),
+                            line, col });
+                    }
+                    tp = TokTp::RLit;
+                }
+                if !self.next_char.ch.is_ascii_digit() { break; }
+            }
+            if tp == TokTp::ILit && text.parse::<i64>().is_err() {
+                return Err(CalcError::Lex {
+                    msg: format!("integer literal '{}' is too large", text),
+                    line, col });
+            }
+            return Ok(self.token(tp, text, line, col));
+        }
+        // s_lit is a '"'-delimited string on a single line; the token's
+        // text holds the decoded contents, without the quotes.
+        if self.next_char.ch == '"' {
+            self.next_char = self.input.getc();
+            loop {
+                match self.next_char.ch {
+                    '"' => break,
+                    '\n' | EOF => return Err(unterminated("string literal", line, col)),
+                    '\\' => {
+                        let ch = self.escape("string literal", line, col)?;
+                        text.push(ch);
+                        continue;
+                    }
+                    ch => text.push(ch),
+                }
+                self.next_char = self.input.getc();
+            }
+            self.next_char = self.input.getc();     // closing quote
+            return Ok(self.token(TokTp::SLit, text, line, col));
+        }
+        // c_lit is a single (possibly escaped) character between '\''s;
+        // the token's text holds the decoded character.
+        if self.next_char.ch == '\'' {
+            self.next_char = self.input.getc();
+            let ch = match self.next_char.ch {
+                '\'' => {
+                    self.next_char = self.input.getc();
+                    return Err(CalcError::Lex {
+                        msg: "empty character literal".to_string(),
+                        line, col });
+                }
+                '\n' | EOF => return Err(unterminated("character literal", line, col)),
+                '\\' => self.escape("character literal", line, col)?,
+                ch => {
+                    self.next_char = self.input.getc();
+                    ch
+                }
+            };
+            if self.next_char.ch != '\'' {
+                // skip the rest of the would-be literal on this line
+                while self.next_char.ch != '\'' {
+                    if self.next_char.ch == '\n' || self.next_char.ch == EOF {
+                        return Err(unterminated("character literal", line, col));
+                    }
+                    self.next_char = self.input.getc();
+                }
+                self.next_char = self.input.getc();
+                return Err(CalcError::Lex {
+                    msg: "character literal holds more than one character"
+                        .to_string(),
+                    line, col });
+            }
+            self.next_char = self.input.getc();     // closing quote
+            text.push(ch);
+            return Ok(self.token(TokTp::CLit, text, line, col));
+        }
+        text.push(self.next_char.ch);
+        let c = self.next_char.ch;
+        self.next_char = self.input.getc();
+        match c {
+            // alone, only in a conditional expression (a < b ? a : b)
+            ':' => {
+                    if self.next_char.ch != '=' {
+                        return Ok(self.token(TokTp::Colon, text, line, col));
+                    }
+                    text.push('=');
+                    self.next_char = self.input.getc();
+                    return Ok(self.token(TokTp::Gets, text, line, col));
+                }
+            '=' => {
+                    if self.next_char.ch != '=' {
+                        return Err(self.expected_eq(c, line, col));
+                    }
+                    text.push('=');
+                    self.next_char = self.input.getc();
+                    return Ok(self.token(TokTp::EqualTo, text, line, col));
+                }
+            '!' => {
+                    if self.next_char.ch != '=' {
+                        return Err(self.expected_eq(c, line, col));
+                    }
+                    text.push('=');
+                    self.next_char = self.input.getc();
+                    return Ok(self.token(TokTp::NEqualTo, text, line, col));
+                }
+            '<' => {
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::LesserEq, text, line, col));
+                    }
+                    // Pascal's spelling of !=
+                    if self.next_char.ch == '>' {
+                        text.push('>');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::NEqualTo, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::Lesser, text, line, col));
+                }
+            '>' => {
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::GreaterEq, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::Greater, text, line, col));
+                }
+            // ++ and -- are always one token, so a - -b needs its space
+            '+' => {
+                    if self.next_char.ch == '+' {
+                        text.push('+');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::Incr, text, line, col));
+                    }
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::PlusEq, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::Plus, text, line, col));
+                }
+            '-' => {
+                    if self.next_char.ch == '-' {
+                        text.push('-');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::Decr, text, line, col));
+                    }
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::MinusEq, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::Minus, text, line, col));
+                }
+            '*' => {
+                    if self.next_char.ch == '*' {
+                        text.push('*');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::Pow, text, line, col));
+                    }
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::TimesEq, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::Times, text, line, col));
+                }
+            '/' => {
+                    if self.next_char.ch == '=' {
+                        text.push('=');
+                        self.next_char = self.input.getc();
+                        return Ok(self.token(TokTp::DivEq, text, line, col));
+                    }
+                    return Ok(self.token(TokTp::DivBy, text, line, col));
+                }
+            '%' => return Ok(self.token(TokTp::Mod, text, line, col)),
+            '&' => return Ok(self.token(TokTp::BitAnd, text, line, col)),
+            '|' => return Ok(self.token(TokTp::BitOr, text, line, col)),
+            '^' => return Ok(self.token(TokTp::BitXor, text, line, col)),
+            '(' => return Ok(self.token(TokTp::LParen, text, line, col)),
+            ')' => return Ok(self.token(TokTp::RParen, text, line, col)),
+            '{' => return Ok(self.token(TokTp::LBrace, text, line, col)),
+            '}' => return Ok(self.token(TokTp::RBrace, text, line, col)),
+            ',' => return Ok(self.token(TokTp::Comma, text, line, col)),
+            ';' => return Ok(self.token(TokTp::Semi, text, line, col)),
+            '?' => return Ok(self.token(TokTp::Question, text, line, col)),
+            _ =>   return Err(CalcError::Lex {
+                        msg: format!("unexpected character '{}' (0x{:x})",
+                            c, c as u32),
+                        line, col }),
+        }
+    }
+
+    // Build a token that ends just before the (already peeked) next_char.
+    // Tokens other than comments never span lines, so only a multi-line
+    // comment ends on a later line than it starts.
+    fn token(&self, tp: TokTp, text: String, line: usize, col: usize) -> Token {
+        let span = Span::new(line, col, self.next_char.line, self.next_char.col);
+        Token { tp, text, span, index: self.count }
+    }
+
+    // Scan a comment, with next_char at its initial '/'.
+    fn comment(&mut self) -> Result<Token, CalcError> {
+        let (line, col) = (self.next_char.line, self.next_char.col);
+        let mut text = String::from("/");
+        self.next_char = self.input.getc();
+        if self.next_char.ch == '/' {
+            while self.next_char.ch != '\n' && self.next_char.ch != EOF {
+                text.push(self.next_char.ch);
+                self.next_char = self.input.getc();
+            }
+        } else {
+            text.push('*');
+            self.next_char = self.input.getc();
+            loop {
+                match self.next_char.ch {
+                    EOF => return Err(CalcError::Lex {
+                            msg: "unterminated comment".to_string(), line, col }),
+                    '*' if self.input.peek2() == '/' => {
+                        text.push_str("*/");
+                        self.input.getc();
+                        self.next_char = self.input.getc();
+                        break;
+                    }
+                    ch => {
+                        text.push(ch);
+                        self.next_char = self.input.getc();
+                    }
+                }
+            }
+        }
+        Ok(self.token(TokTp::Comment, text, line, col))
+    }
+
+    // Decode an escape sequence inside a literal (what) that began at
+    // line/col.  Called with next_char at the backslash; consumes the
+    // whole escape.
+    fn escape(&mut self, what: &str, line: usize, col: usize) -> Result<char, CalcError> {
+        self.next_char = self.input.getc();
+        let ch = match self.next_char.ch {
+            '"'  => '"',
+            '\'' => '\'',
+            '\\' => '\\',
+            'n'  => '\n',
+            '\n' | EOF => return Err(unterminated(what, line, col)),
+            other => {
+                let esc = CalcError::Lex {
+                    msg: format!("unknown escape sequence '\\{}'", other),
+                    line: self.next_char.line, col: self.next_char.col };
+                self.next_char = self.input.getc();
+                return Err(esc);
+            }
+        };
+        self.next_char = self.input.getc();
+        Ok(ch)
+    }
+
+    // Error for a '=' or '!' that isn't followed by '='.
+    // Leaves the offending character unconsumed.
+    fn expected_eq(&self, c: char, line: usize, col: usize) -> CalcError {
+        CalcError::Lex {
+            msg: format!("expected '=' after '{}', got '{}' (0x{:x})",
+                c, self.next_char.ch, self.next_char.ch as u32),
+            line, col,
+        }
+    }
+
+} // end impl Scanner
+
+// Error for a literal (what) that reaches the end of its line, or of the
+// input, before its closing quote.  The position is that of the opening
+// quote, which may be far from where the scanner noticed.
+fn unterminated(what: &str, line: usize, col: usize) -> CalcError {
+    CalcError::Lex { msg: format!("unterminated {}", what), line, col }
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn is_combining_mark(c: char) -> bool {
+    ::unicode_normalization::char::is_combining_mark(c)
+}
+
+#[cfg(feature = "unicode-normalization")]
+fn nfc(text: String) -> String {
+    use ::unicode_normalization::UnicodeNormalization;
+    text.nfc().collect()
+}
+
+#[cfg(not(feature = "unicode-normalization"))]
+fn is_combining_mark(_c: char) -> bool {
+    false
+}
+
+#[cfg(not(feature = "unicode-normalization"))]
+fn nfc(text: String) -> String {
+    text
+}
+
+// Yields tokens up to, but not including, End.  Iteration also stops at
+// the first lexical error; callers that need to see the error should
+// call scan() directly.  The iterator borrows the scanner rather than
+// being the scanner, so Iterator::scan doesn't hide Scanner::scan.
+pub struct Tokens<'a> {
+    scanner: &'a mut Scanner,
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.scanner.done {
+            return None;
+        }
+        match self.scanner.scan() {
+            Ok(tok) if tok.tp != TokTp::End => Some(tok),
+            _ => {
+                self.scanner.done = true;
+                None
+            }
+        }
+    }
+}
+
+/// Scans `src` and returns its tokens, excluding the final `End`.
+///
+/// Like `Scanner::tokens`, this stops at the first lexical error, so
+/// the result is a prefix of the program; use `Scanner::scan` to see
+/// the error itself.
+///
+/// ```
+/// use calc::scanner::{tokenize, TokTp};
+///
+/// let toks = tokenize("x := 3 + 4");
+/// let tps: Vec<TokTp> = toks.iter().map(|t| t.tp).collect();
+/// assert_eq!(tps, [TokTp::Ident, TokTp::Gets, TokTp::ILit,
+///                  TokTp::Plus, TokTp::ILit]);
+/// assert_eq!(toks[2].text, "3");
+/// ```
+pub fn tokenize(src: &str) -> Vec<Token> {
+    Scanner::from_str(src).tokens().collect()
+}
+
+// try_tokenize() gives up after this many tokens.
+pub const MAX_TOKENS: usize = 1_000_000;
+
+// Why try_tokenize() failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanError {
+    Lex(CalcError),         // the first lexical error
+    TooManyTokens(usize),   // gave up after this many
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScanError::Lex(ref e) => e.fmt(f),
+            ScanError::TooManyTokens(n) => write!(f, "more than {} tokens", n),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+/// Scans `src` into its tokens, excluding the final `End`, or returns
+/// the first lexical error.
+///
+/// Unlike `tokenize`, this reports the error rather than stopping short,
+/// and gives up after `MAX_TOKENS` tokens, so it is safe to call on
+/// arbitrary input (e.g. from a fuzzer).  It never touches stdin.
+pub fn try_tokenize(src: &str) -> Result<Vec<Token>, ScanError> {
+    try_tokenize_at_most(src, MAX_TOKENS)
+}
+
+fn try_tokenize_at_most(src: &str, max: usize) -> Result<Vec<Token>, ScanError> {
+    let mut scanner = Scanner::from_str(src);
+    let mut toks = Vec::new();
+    loop {
+        let tok = scanner.scan().map_err(ScanError::Lex)?;
+        if tok.tp == TokTp::End {
+            return Ok(toks);
+        }
+        if toks.len() == max {
+            return Err(ScanError::TooManyTokens(max));
+        }
+        toks.push(tok);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterator_collects_token_types() {
+        let mut scanner = Scanner::from_input(
+            Input::from_str("read int n\nwrite n * 2.5\n"));
+        let tps: Vec<TokTp> = scanner.tokens().map(|tok| tok.tp).collect();
+        assert_eq!(tps, vec![TokTp::Read, TokTp::Int, TokTp::Ident,
+            TokTp::Write, TokTp::Ident, TokTp::Times, TokTp::RLit]);
+    }
+
+    #[test]
+    fn scans_from_any_reader() {
+        let from_bytes: Vec<Token> =
+            Scanner::from_reader(&b"x := 1 ; y"[..]).tokens().collect();
+        assert_eq!(from_bytes, tokenize("x := 1 ; y"));
+        let texts: Vec<String> =
+            Scanner::from_str("a\n  b").tokens().map(|tok| tok.text).collect();
+        assert_eq!(texts, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn tokens_compare_whole() {
+        let mut scanner = Scanner::from_str("x := 10");
+        assert_eq!(scanner.scan(), Ok(Token { tp: TokTp::Ident, text: "x".into(),
+            span: Span::new(1, 0, 1, 1), index: 0 }));
+        let gets = scanner.scan().unwrap();
+        assert_eq!(gets.clone(), gets);
+        let ten = scanner.scan().unwrap();
+        assert_eq!(ten, Token { tp: TokTp::ILit, text: "10".into(), span: Span::new(1, 5, 1, 7),
+            index: 2 });
+        // the same text elsewhere is a different token
+        assert_ne!(ten, Scanner::from_str("10").tokens().next().unwrap());
+    }
+
+    #[test]
+    fn peek_does_not_consume() {
+        let mut scanner = Scanner::from_str("x := 1\n@ y");
+        let x = scanner.peek().unwrap().clone();
+        assert_eq!(scanner.peek(), Ok(&x));
+        assert_eq!(scanner.scan(), Ok(x));
+        // scanning without peeking in between skips nothing
+        assert_eq!(scanner.scan().unwrap().tp, TokTp::Gets);
+        assert_eq!(scanner.peek().unwrap().text, "1");
+        assert_eq!(scanner.peek().unwrap().index, 2);
+        assert_eq!(scanner.scan().unwrap().text, "1");
+        // an error is peeked at too, and comes back from scan just once
+        let err = scanner.peek().unwrap_err().clone();
+        assert_eq!(err.line(), 2);
+        assert_eq!(scanner.scan(), Err(err));
+        assert_eq!(scanner.peek().unwrap().text, "y");
+        assert_eq!(scanner.tokens().next().map(|tok| tok.index), Some(3));
+        assert_eq!(scanner.peek().unwrap().tp, TokTp::End);
+        assert_eq!(scanner.tokens().next(), None);
+    }
+
+    #[test]
+    fn try_tokenize_reports_errors() {
+        let toks = try_tokenize("x := 1 // done").unwrap();
+        assert_eq!(toks.len(), 3);
+        assert_eq!(try_tokenize("x := 1 @ 2").unwrap_err(), ScanError::Lex(CalcError::Lex {
+            msg: "unexpected character '@' (0x40)".to_string(), line: 1, col: 7 }));
+        assert_eq!(try_tokenize_at_most("a b c", 3).unwrap().len(), 3);
+        assert_eq!(try_tokenize_at_most("a b c d", 3).unwrap_err(), ScanError::TooManyTokens(3));
+        assert_eq!(ScanError::TooManyTokens(3).to_string(), "more than 3 tokens");
+    }
+
+    #[test]
+    fn try_tokenize_survives_random_bytes() {
+        const INTERESTING: &[u8] = b"0123456789._ \n\t\\\"'/*:=<>!-+(){}abz";
+        // a fixed linear congruential generator, so failures reproduce
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 56) as u8
+        };
+        for len in 0..300 {
+            let bytes: Vec<u8> = (0..len).map(|_| next()).collect();
+            let src = String::from_utf8_lossy(&bytes);
+            let _ = try_tokenize(&src);
+            // and the same, biased toward characters the scanner cares about
+            let src: String = bytes.iter()
+                .map(|&b| INTERESTING[b as usize % INTERESTING.len()] as char)
+                .collect();
+            let _ = try_tokenize(&src);
+        }
+    }
+
+    fn tps(src: &str) -> Vec<TokTp> {
+        Scanner::from_input(Input::from_str(src)).tokens().map(|tok| tok.tp).collect()
+    }
+
+    fn texts(src: &str) -> Vec<String> {
+        Scanner::from_input(Input::from_str(src)).tokens().map(|tok| tok.text).collect()
+    }
+
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(tps("read write if elsif else fi do od check assert for to const int real \
+                trunc float"),
+            vec![TokTp::Read, TokTp::Write, TokTp::If, TokTp::Elsif, TokTp::Else, TokTp::Fi,
+                TokTp::Do, TokTp::Od, TokTp::Check, TokTp::Assert, TokTp::For, TokTp::To, TokTp::Const,
+                TokTp::Int, TokTp::Real, TokTp::Trunc, TokTp::Float]);
+        // a keyword prefix or suffix doesn't make a keyword
+        assert_eq!(tps("ifx xif do2 reads"), vec![TokTp::Ident; 4]);
+        assert_eq!(texts("ifx xif do2 reads"), vec!["ifx", "xif", "do2", "reads"]);
+        // mod is %, but keeps its spelling
+        assert_eq!(tps("a mod b % c"),
+            vec![TokTp::Ident, TokTp::Mod, TokTp::Ident, TokTp::Mod, TokTp::Ident]);
+        assert_eq!(texts("a mod b"), vec!["a", "mod", "b"]);
+        assert_eq!(tps("model amod mod2 modulo"), vec![TokTp::Ident; 4]);
+        assert_eq!(tps("print input printing inputs"),
+            vec![TokTp::Write, TokTp::Read, TokTp::Ident, TokTp::Ident]);
+        assert_eq!(texts("print input"), vec!["print", "input"]);
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(tps(":= == != <= >= < >"),
+            vec![TokTp::Gets, TokTp::EqualTo, TokTp::NEqualTo, TokTp::LesserEq,
+                TokTp::GreaterEq, TokTp::Lesser, TokTp::Greater]);
+        assert_eq!(tps("+ - * / % ( ) ** , ; { }"),
+            vec![TokTp::Plus, TokTp::Minus, TokTp::Times, TokTp::DivBy, TokTp::Mod,
+                TokTp::LParen, TokTp::RParen, TokTp::Pow, TokTp::Comma, TokTp::Semi,
+                TokTp::LBrace, TokTp::RBrace]);
+        assert_eq!(texts("2*3**4***5* *6"), vec!["2", "*", "3", "**", "4", "**", "*", "5",
+            "*", "*", "6"]);
+        assert_eq!(tps("<> < > <=>"),
+            vec![TokTp::NEqualTo, TokTp::Lesser, TokTp::Greater, TokTp::LesserEq,
+                TokTp::Greater]);
+        assert_eq!(texts("a<>b"), vec!["a", "<>", "b"]);
+        // no white space needed between tokens
+        assert_eq!(tps("x:=y<=(z)"),
+            vec![TokTp::Ident, TokTp::Gets, TokTp::Ident, TokTp::LesserEq,
+                TokTp::LParen, TokTp::Ident, TokTp::RParen]);
+        assert_eq!(texts("a>=b!=c"), vec!["a", ">=", "b", "!=", "c"]);
+        assert_eq!(tps("x++ y-- a - -b a+++b"),
+            vec![TokTp::Ident, TokTp::Incr, TokTp::Ident, TokTp::Decr,
+                TokTp::Ident, TokTp::Minus, TokTp::Minus, TokTp::Ident,
+                TokTp::Ident, TokTp::Incr, TokTp::Plus, TokTp::Ident]);
+        assert_eq!(tps("a+=b-=c*=d/=e"),
+            vec![TokTp::Ident, TokTp::PlusEq, TokTp::Ident, TokTp::MinusEq,
+                TokTp::Ident, TokTp::TimesEq, TokTp::Ident, TokTp::DivEq, TokTp::Ident]);
+        // && is two tokens, for now
+        assert_eq!(tps("a&b|c ^ d&&e"),
+            vec![TokTp::Ident, TokTp::BitAnd, TokTp::Ident, TokTp::BitOr, TokTp::Ident,
+                TokTp::BitXor, TokTp::Ident, TokTp::BitAnd, TokTp::BitAnd, TokTp::Ident]);
+        // only when the '=' is right there
+        assert_eq!(tps("x * == y **==z /:= w"),
+            vec![TokTp::Ident, TokTp::Times, TokTp::EqualTo, TokTp::Ident, TokTp::Pow,
+                TokTp::EqualTo, TokTp::Ident, TokTp::DivBy, TokTp::Gets, TokTp::Ident]);
+        // a colon is only the start of := if an = follows
+        assert_eq!(tps("a?b:c:=d: =="),
+            vec![TokTp::Ident, TokTp::Question, TokTp::Ident, TokTp::Colon, TokTp::Ident,
+                TokTp::Gets, TokTp::Ident, TokTp::Colon, TokTp::EqualTo]);
+    }
+
+    #[test]
+    fn numeric_literals() {
+        assert_eq!(tps("42 3.14 0.5 7"),
+            vec![TokTp::ILit, TokTp::RLit, TokTp::RLit, TokTp::ILit]);
+        assert_eq!(texts("42 3.14 0.5 7"), vec!["42", "3.14", "0.5", "7"]);
+        // a number runs straight into a following identifier or operator
+        assert_eq!(tps("3.14x-1"),
+            vec![TokTp::RLit, TokTp::Ident, TokTp::Minus, TokTp::ILit]);
+        for bad in &["3.", "1.2.3", "99999999999999999999"] {
+            let mut scanner = Scanner::from_input(Input::from_str(bad));
+            assert!(scanner.scan().is_err(), "{} should not scan", bad);
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let src = "x // one\n/* two\n  lines */ y / z /**/ /*/ 3 */";
+        assert_eq!(texts(src), vec!["x", "y", "/", "z"]);
+        let mut scanner = Scanner::from_input(Input::from_str(src));
+        scanner.set_keep_comments(true);
+        let toks: Vec<(TokTp, String, usize, usize)> =
+            scanner.tokens()
+                .map(|tok| (tok.tp, tok.text, tok.span.start_line, tok.span.start_col))
+                .collect();
+        let comment = |text: &str, line, col| (TokTp::Comment, text.to_string(), line, col);
+        assert_eq!(toks, vec![
+            (TokTp::Ident, "x".to_string(), 1, 0),
+            comment("// one", 1, 2),
+            comment("/* two\n  lines */", 2, 0),
+            (TokTp::Ident, "y".to_string(), 3, 11),
+            (TokTp::DivBy, "/".to_string(), 3, 13),
+            (TokTp::Ident, "z".to_string(), 3, 15),
+            comment("/**/", 3, 17),
+            comment("/*/ 3 */", 3, 22),
+        ]);
+
+        let mut scanner = Scanner::from_input(Input::from_str("x /* no end\n"));
+        scanner.scan().unwrap();
+        assert_eq!(scanner.scan().unwrap_err(), CalcError::Lex {
+            msg: "unterminated comment".to_string(), line: 1, col: 2 });
+        assert_eq!(scanner.scan().unwrap().tp, TokTp::End);
+    }
+
+    #[test]
+    fn last_token_without_newline() {
+        let scan_all = |src: &str| {
+            let mut scanner = Scanner::from_input(Input::from_str(src));
+            scanner.set_keep_comments(true);
+            let mut toks = Vec::new();
+            loop {
+                let tok = scanner.scan().unwrap();
+                toks.push((tok.tp, tok.text, tok.span.start_line, tok.span.start_col));
+                if tok.tp == TokTp::End { return toks; }
+            }
+        };
+        assert_eq!(scan_all("42"), vec![
+            (TokTp::ILit, "42".to_string(), 1, 0),
+            (TokTp::End, String::new(), 2, 0),
+        ]);
+        assert_eq!(scan_all("x := 1 // done"), vec![
+            (TokTp::Ident, "x".to_string(), 1, 0),
+            (TokTp::Gets, ":=".to_string(), 1, 2),
+            (TokTp::ILit, "1".to_string(), 1, 5),
+            (TokTp::Comment, "// done".to_string(), 1, 7),
+            (TokTp::End, String::new(), 2, 0),
+        ]);
+        assert_eq!(tps("2.5"), vec![TokTp::RLit]);
+        assert_eq!(tps("x /* c */"), vec![TokTp::Ident]);
+    }
+
+    #[test]
+    fn token_json() {
+        let toks: Vec<Token> = Scanner::from_input(
+            Input::from_str(r#"x := "a \"q\"\n""#)).tokens().collect();
+        assert_eq!(toks[0].to_json(), r#"{"tp":"Ident","text":"x","line":1,"col":0}"#);
+        assert_eq!(toks[1].to_json(), r#"{"tp":"Gets","text":":=","line":1,"col":2}"#);
+        assert_eq!(toks[2].to_json(),
+            r#"{"tp":"SLit","text":"a \"q\"\n","line":1,"col":5}"#);
+    }
+
+    #[test]
+    fn token_type_display() {
+        assert_eq!(TokTp::NEqualTo.to_string(), "NEqualTo");
+        assert_eq!(format!("[{:<6}|{:>6}]", TokTp::If, TokTp::End), "[If    |   End]");
+    }
+
+    #[test]
+    fn continued_lines() {
+        let toks = tokenize("x := a + \\\n  b");
+        let got: Vec<(TokTp, usize, usize)> =
+            toks.into_iter().map(|tok| (tok.tp, tok.span.start_line, tok.span.start_col)).collect();
+        assert_eq!(got, vec![(TokTp::Ident, 1, 0), (TokTp::Gets, 1, 2), (TokTp::Ident, 1, 5),
+            (TokTp::Plus, 1, 7), (TokTp::Ident, 2, 2)]);
+        // anywhere else, a backslash is just a bad character
+        let mut scanner = Scanner::from_input(Input::from_str("a \\ b"));
+        scanner.scan().unwrap();
+        assert_eq!(scanner.scan().unwrap_err(), CalcError::Lex {
+            msg: "unexpected character '\\' (0x5c)".to_string(), line: 1, col: 2 });
+    }
+
+    #[test]
+    fn tokens_are_numbered() {
+        let mut scanner = Scanner::from_input(Input::from_str("a @ // c\nb /* d */ c"));
+        let mut indices = Vec::new();
+        loop {
+            match scanner.scan() {
+                Ok(tok) => {
+                    indices.push((tok.text, tok.index));
+                    if tok.tp == TokTp::End { break; }
+                }
+                Err(_) => indices.push(("error".to_string(), 99)),
+            }
+        }
+        // neither the bad character nor the skipped comments count
+        assert_eq!(indices, vec![("a".to_string(), 0), ("error".to_string(), 99),
+            ("b".to_string(), 1), ("c".to_string(), 2), (String::new(), 3)]);
+        let indices: Vec<usize> = tokenize("x := 1").iter().map(|tok| tok.index).collect();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    #[test]
+    fn identifiers_are_nfc() {
+        // decomposed, then precomposed
+        assert_eq!(texts("cafe\u{301} := caf\u{e9}"), vec!["caf\u{e9}", ":=", "caf\u{e9}"]);
+        assert_eq!(tps("cafe\u{301}"), vec![TokTp::Ident]);
+        let toks: Vec<Token> = Scanner::from_input(
+            Input::from_str("write \"e\u{301}\"")).tokens().collect();
+        assert_eq!(toks[1].text, "e\u{301}");
+    }
+
+    #[test]
+    fn non_default_config() {
+        let config = ScannerConfig { case_insensitive: true, tab_width: 4, keep_comments: true,
+            allow_underscores: true };
+        let mut scanner = Scanner::from_input_with_config(
+            Input::from_str("\tWRITE 1_000 // c\n\t\t2_5.0_1"), config.clone());
+        assert_eq!(scanner.config(), &config);
+        let toks: Vec<(TokTp, String, usize)> =
+            scanner.tokens().map(|tok| (tok.tp, tok.text, tok.span.start_col)).collect();
+        assert_eq!(toks, vec![
+            (TokTp::Write, "WRITE".to_string(), 4),
+            (TokTp::ILit, "1000".to_string(), 10),
+            (TokTp::Comment, "// c".to_string(), 16),
+            (TokTp::RLit, "25.01".to_string(), 8),
+        ]);
+        // an underscore has to be between digits
+        for src in &["1__0", "1_", "1_.5"] {
+            let mut scanner = Scanner::from_input_with_config(Input::from_str(src),
+                ScannerConfig { allow_underscores: true, ..ScannerConfig::default() });
+            match scanner.scan() {
+                Err(CalcError::Lex { ref msg, .. }) => {
+                    assert!(msg.ends_with("expected digit after '_'"), "{}", msg)
+                }
+                other => panic!("{}: {:?}", src, other),
+            }
+        }
+        // and by default isn't part of a number at all
+        assert_eq!(texts("1_000"), vec!["1"]);
+        assert_eq!(Scanner::new_case_insensitive().config().tab_width, 8);
+    }
+
+    #[test]
+    fn zero_tab_width_is_one() {
+        let mut scanner = Scanner::from_input_with_config(Input::from_str("\t\tx"),
+            ScannerConfig { tab_width: 0, ..ScannerConfig::default() });
+        assert_eq!(scanner.config().tab_width, 1);
+        let toks: Vec<Token> = scanner.tokens().collect();
+        assert_eq!(toks[0].span.start_col, 2);
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let config = ScannerConfig { case_insensitive: true, ..ScannerConfig::default() };
+        let mut scanner = Scanner::from_input_with_config(
+            Input::from_str("READ Read read Foo"), config);
+        let toks: Vec<Token> = scanner.tokens().collect();
+        assert!(toks[..3].iter().all(|tok| tok.tp == TokTp::Read));
+        assert_eq!(toks[1].text, "Read");
+        assert_eq!(toks[3].tp, TokTp::Ident);
+        assert_eq!(toks[3].text, "Foo");
+
+        let mut scanner = Scanner::from_input(Input::from_str("READ read"));
+        let tps: Vec<TokTp> = scanner.tokens().map(|tok| tok.tp).collect();
+        assert_eq!(tps, vec![TokTp::Ident, TokTp::Read]);
+    }
+
+    #[test]
+    fn string_literals() {
+        let mut scanner = Scanner::from_input(
+            Input::from_str(r#"write "say \"hi\"\\\n" x"#));
+        scanner.scan().unwrap();
+        let tok = scanner.scan().unwrap();
+        assert_eq!(tok.tp, TokTp::SLit);
+        assert_eq!(tok.text, "say \"hi\"\\\n");
+        assert_eq!(scanner.scan().unwrap().tp, TokTp::Ident);
+
+        // error reports where the literal began, and scanning resumes
+        let mut scanner = Scanner::from_input(
+            Input::from_str("x := 1\nwrite \"oops\nx"));
+        for _ in 0..4 { scanner.scan().unwrap(); }
+        let err = scanner.scan().unwrap_err();
+        assert_eq!((err.line(), err.col()), (2, Some(6)));
+        assert_eq!(scanner.scan().unwrap().text, "x");
+    }
+
+    #[test]
+    fn char_literals() {
+        let mut scanner = Scanner::from_input(
+            Input::from_str(r"'a' '\n' '' 'ab' x 'c"));
+        let tok = scanner.scan().unwrap();
+        assert_eq!((tok.tp, tok.text), (TokTp::CLit, "a".to_string()));
+        assert_eq!(scanner.scan().unwrap().text, "\n");
+        assert_eq!(scanner.scan().unwrap_err().col(), Some(9));
+        assert_eq!(scanner.scan().unwrap_err().col(), Some(12));
+        assert_eq!(scanner.scan().unwrap().text, "x");
+        assert_eq!(scanner.scan().unwrap_err(),
+            unterminated("character literal", 1, 19));
+    }
+
+    #[test]
+    fn unterminated_literals() {
+        let first_error = |src: &str| {
+            let mut scanner = Scanner::from_str(src);
+            loop {
+                match scanner.scan() {
+                    Ok(ref tok) if tok.tp == TokTp::End => panic!("no error in {:?}", src),
+                    Ok(_) => {}
+                    Err(e) => return e.to_string(),
+                }
+            }
+        };
+        // stopped by the end of the line
+        assert_eq!(first_error("x := 1\nwrite \"abc\nwrite 2"),
+            "unterminated string literal on line 2, col 6");
+        // by the end of the input
+        assert_eq!(first_error("write \"abc"),
+            "unterminated string literal on line 1, col 6");
+        assert_eq!(first_error("write \"a\\"),
+            "unterminated string literal on line 1, col 6");
+        assert_eq!(first_error("\tx := 'a"),
+            "unterminated character literal on line 1, col 13");
+        assert_eq!(first_error("x := '\\"),
+            "unterminated character literal on line 1, col 5");
+    }
+
+    #[test]
+    fn tokens_span_their_lexemes() {
+        let mut scanner = Scanner::from_input(Input::from_str("abc := 12.5 >= x"));
+        let spans: Vec<(usize, usize)> =
+            scanner.tokens().map(|tok| (tok.span.start_col, tok.span.end_col)).collect();
+        assert_eq!(spans, vec![(0, 3), (4, 6), (7, 11), (12, 14), (15, 16)]);
+        // a comment can end on a later line than it starts
+        let mut scanner = Scanner::from_input(Input::from_str("x /* a
+ b */ y"));
+        scanner.set_keep_comments(true);
+        let spans: Vec<String> = scanner.tokens().map(|tok| tok.span.to_string()).collect();
+        assert_eq!(spans, vec!["1:0-1:1", "1:2-2:5", "2:6-2:7"]);
+    }
+}
@@ -0,0 +1,692 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Semantic checks
+//
+//  Walks the AST after a successful parse, keeping a symbol table of
+//  declared variables and their types.  (Uses of undeclared variables are
+//  found by a separate, simpler pass, written as a Visitor.)  A variable
+//  is declared by `int`/`real`, by a typed `read`, or as the int variable
+//  of a `for` loop; the bodies of `if`, `else`, `do` and `for` are nested
+//  scopes.  A `const` declaration is like `int`/`real`, but the name
+//  can't be assigned or read into afterward.  A `check` exits the
+//  innermost `do` or `for` loop, so must be inside one.
+//  Every expression has type int or real.  An int meeting a real is
+//  promoted to real, as if float() had been applied to it:
+//
+//      int  op int   -->  int        (op is + - * / % ** & | or ^)
+//      int  op real  -->  real       (but & | and ^ take only ints)
+//      real op int   -->  real
+//      real op real  -->  real
+//      int  := int   ok              (any assignment, declaration,
+//      real := int   ok, promoted     or const)
+//      real := real  ok
+//      int  := real  error: use trunc()
+//
+//  Promotion never goes the other way, since that would lose the
+//  fraction, and a comparison's operands must still have the same type,
+//  as must the two branches of a conditional expression (c ? a : b).
+//
+//  More passes, run once the program is known to be well typed, look for
+//  variables that are given a value but never read, for declarations
+//  that hide one in an enclosing scope, and for variables declared
+//  without a value (int x) that are read before anything is assigned to
+//  them.  These are only warnings: the program can still run, with such
+//  a variable starting at 0.
+//
+
+use crate::ast::*;
+use crate::error::CalcError;
+use crate::visit::{self, Visitor};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+
+struct Checker {
+    scopes: Vec<HashMap<String, Symbol>>,   // innermost last
+    loops: usize,       // do and for loops around the current statement
+    errors: Vec<CalcError>,
+}
+
+#[derive(Clone, Copy)]
+struct Symbol {
+    tp: Type,
+    constant: bool,
+}
+
+// Check a whole program, returning every error found, in order of line.
+pub fn check(stmts: &[Stmt]) -> Vec<CalcError> {
+    let mut resolver = Resolver::new();
+    resolver.visit_program(stmts);
+    let mut checker = Checker { scopes: vec![HashMap::new()], loops: 0, errors: vec![] };
+    checker.stmt_list(stmts);
+    let mut errors = resolver.errors;
+    errors.extend(checker.errors);
+    errors.sort_by_key(CalcError::line);     // stable: each pass is in order
+    errors
+}
+
+// Resolves each variable to its declaration, scoped as in Checker.
+// Reports (as errors) uses of, or assignments to, variables that aren't
+// declared in an enclosing scope, and (as warnings) declarations that
+// shadow one in an enclosing scope.
+struct Resolver {
+    scopes: Vec<HashSet<String>>,   // innermost last
+    errors: Vec<CalcError>,
+    warnings: Vec<Warning>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Self { scopes: vec![HashSet::new()], errors: vec![], warnings: vec![] }
+    }
+
+    fn declare(&mut self, name: &str, line: usize) {
+        let (innermost, outer) = self.scopes.split_last_mut().unwrap();
+        if outer.iter().any(|s| s.contains(name)) {
+            self.warnings.push(Warning {
+                msg: format!("declaration of '{}' shadows an earlier one", name), line });
+        }
+        innermost.insert(name.to_string());
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_body(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashSet::new());
+        visit::walk_stmts(self, stmts);
+        self.scopes.pop();
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match *stmt {
+            // the new variable isn't in scope in its own initializer
+            Stmt::Decl { ref name, ref expr, .. } => {
+                if let Some(ref expr) = *expr {
+                    self.visit_expr(expr);
+                }
+                self.declare(name, stmt.line());
+            }
+            Stmt::Const { ref name, ref expr, .. } => {
+                self.visit_expr(expr);
+                self.declare(name, stmt.line());
+            }
+            Stmt::Read { tp: Some(_), ref names, .. } => {
+                for name in names {
+                    self.declare(name, stmt.line());
+                }
+            }
+            Stmt::For { ref var, ref from, ref to, ref body, .. } => {
+                self.visit_expr(from);
+                self.visit_expr(to);
+                self.scopes.push(HashSet::new());
+                self.declare(var, stmt.line());
+                self.visit_body(body);
+                self.scopes.pop();
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_target(&mut self, name: &str, span: Span) {
+        self.visit_ident(name, span);
+    }
+
+    fn visit_ident(&mut self, name: &str, span: Span) {
+        if !self.scopes.iter().any(|s| s.contains(name)) {
+            self.errors.push(CalcError::Semantic {
+                msg: format!("use of undeclared variable '{}'", name), line: span.start_line });
+        }
+    }
+}
+
+impl Checker {
+    fn error(&mut self, msg: String, line: usize) {
+        self.errors.push(CalcError::Semantic { msg, line });
+    }
+
+    fn lookup(&self, name: &str) -> Option<Symbol> {
+        self.scopes.iter().rev().filter_map(|s| s.get(name)).next().cloned()
+    }
+
+    fn declare(&mut self, name: &str, tp: Type, line: usize) {
+        self.declare_symbol(name, Symbol { tp, constant: false }, line);
+    }
+
+    fn declare_symbol(&mut self, name: &str, symbol: Symbol, line: usize) {
+        let redeclared = self.scopes.last_mut().unwrap()
+            .insert(name.to_string(), symbol).is_some();
+        if redeclared {
+            self.error(format!("redeclaration of variable '{}'", name), line);
+        }
+    }
+
+    // The type of a variable, or None if it's undeclared (which
+    // Resolver reports).
+    fn use_var(&self, name: &str) -> Option<Type> {
+        self.lookup(name).map(|s| s.tp)
+    }
+
+    // Like use_var(), for a variable about to be stored into.
+    fn set_var(&mut self, name: &str, line: usize) -> Option<Type> {
+        match self.lookup(name) {
+            Some(Symbol { constant: true, .. }) => {
+                self.error(format!("cannot assign to constant '{}'", name), line);
+                None
+            }
+            _ => self.use_var(name),
+        }
+    }
+
+    fn stmt_list(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn nested(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.stmt_list(stmts);
+        self.scopes.pop();
+    }
+
+    // Complain if a value of type from is stored in a variable of type to.
+    fn assign(&mut self, name: &str, to: Option<Type>, from: Option<Type>, line: usize) {
+        if let (Some(to), Some(from)) = (to, from) {
+            if !assignable(from, to) {
+                self.error(format!("type mismatch: cannot assign {} to {} '{}'",
+                    from, to, name), line);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        let line = stmt.line();
+        match *stmt {
+            Stmt::Assign { ref name, ref expr, .. } => {
+                let from = self.expr(expr, line);
+                let to = self.set_var(name, line);
+                self.assign(name, to, from, line);
+            }
+            Stmt::Decl { tp, ref name, ref expr, .. } => {
+                // the new variable isn't in scope in its own initializer
+                let from = expr.as_ref().and_then(|expr| self.expr(expr, line));
+                self.declare(name, tp, line);
+                self.assign(name, Some(tp), from, line);
+            }
+            Stmt::Const { tp, ref name, ref expr, .. } => {
+                let from = self.expr(expr, line);
+                self.declare_symbol(name, Symbol { tp, constant: true }, line);
+                self.assign(name, Some(tp), from, line);
+            }
+            Stmt::Read { tp, ref names, .. } => {
+                for name in names {
+                    match tp {
+                        Some(tp) => self.declare(name, tp, line),
+                        None => { self.set_var(name, line); }
+                    }
+                }
+            }
+            Stmt::Write { ref args, .. } => {
+                for arg in args {
+                    if let WriteArg::Expr(ref expr) = *arg {
+                        self.expr(expr, line);
+                    }
+                }
+            }
+            Stmt::If { ref branches, ref else_body, .. } => {
+                for (cond, body) in branches {
+                    self.cond(cond, line);
+                    self.nested(body);
+                }
+                if let Some(ref else_body) = *else_body {
+                    self.nested(else_body);
+                }
+            }
+            Stmt::Do { ref body, .. } => {
+                self.loops += 1;
+                self.nested(body);
+                self.loops -= 1;
+            }
+            Stmt::For { ref var, ref from, ref to, ref body, .. } => {
+                for bound in &[from, to] {
+                    if let Some(Type::Real) = self.expr(bound, line) {
+                        self.error("type mismatch: for loop bounds must be int".to_string(),
+                            line);
+                    }
+                }
+                // the loop variable is an int local to the loop
+                self.scopes.push(HashMap::new());
+                self.declare(var, Type::Int, line);
+                self.loops += 1;
+                self.nested(body);
+                self.loops -= 1;
+                self.scopes.pop();
+            }
+            Stmt::Check { ref cond, .. } => {
+                if self.loops == 0 {
+                    self.error("check outside of a do or for loop".to_string(), line);
+                }
+                self.cond(cond, line);
+            }
+            Stmt::Assert { ref cond, .. } => self.cond(cond, line),
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond, line: usize) {
+        let lhs = self.expr(&cond.lhs, line);
+        let rhs = self.expr(&cond.rhs, line);
+        if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+            if lhs != rhs {
+                self.error(format!("type mismatch: cannot compare {} and {}", lhs, rhs),
+                    line);
+            }
+        }
+    }
+
+    // trunc takes a real to an int, and float an int to a real.
+    fn convert(&mut self, func: &str, operand: &Expr, from: Type, line: usize)
+            -> Option<Type> {
+        let to = if from == Type::Real { Type::Int } else { Type::Real };
+        match self.expr(operand, line) {
+            Some(tp) if tp != from => {
+                self.error(format!("type mismatch: {} expects {}, found {}",
+                    func, from, tp), line);
+                None
+            }
+            Some(_) => Some(to),
+            None => None,
+        }
+    }
+
+    // Returns the type of expr, or None if it contains an error that has
+    // already been reported.  Line is that of the enclosing statement.
+    fn expr(&mut self, expr: &Expr, line: usize) -> Option<Type> {
+        match *expr {
+            Expr::Ident { ref name, .. } => self.use_var(name),
+            Expr::ILit(..) => Some(Type::Int),
+            Expr::RLit(..) => Some(Type::Real),
+            Expr::Neg { ref operand, .. } => self.expr(operand, line),
+            Expr::Trunc(ref operand, _) => self.convert("trunc", operand, Type::Real, line),
+            Expr::Float(ref operand, _) => self.convert("float", operand, Type::Int, line),
+            Expr::Binary { op, ref lhs, ref rhs, .. } => {
+                let lhs = self.expr(lhs, line);
+                let rhs = self.expr(rhs, line);
+                match (lhs, rhs) {
+                    (Some(Type::Real), _) | (_, Some(Type::Real)) if op.is_bitwise() => {
+                        self.error("type mismatch: bitwise operators take int, found real"
+                            .to_string(), line);
+                        None
+                    }
+                    (Some(Type::Int), Some(Type::Int)) => Some(Type::Int),
+                    (Some(_), Some(_)) => Some(Type::Real),    // promoting an int
+                    _ => None,
+                }
+            }
+            // a chained assignment has the type of its variable
+            Expr::Assign { ref name, ref value, span } => {
+                let line = span.start_line;
+                let from = self.expr(value, line);
+                let to = self.set_var(name, line);
+                self.assign(name, to, from, line);
+                match (from, to) {
+                    (Some(from), Some(to)) if !assignable(from, to) => None,
+                    _ => to,
+                }
+            }
+            // both branches must have the same type, which is its type
+            Expr::Ternary { ref cond, ref then, ref otherwise, .. } => {
+                self.cond(cond, line);
+                match (self.expr(then, line), self.expr(otherwise, line)) {
+                    (Some(a), Some(b)) if a != b => {
+                        self.error(format!("type mismatch: conditional branches are {} and {}",
+                            a, b), line);
+                        None
+                    }
+                    (Some(a), Some(_)) => Some(a),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+// Whether a value of type from can be stored in a variable of type to:
+// the same type, or an int promoted to real.
+fn assignable(from: Type, to: Type) -> bool {
+    from == to || (from == Type::Int && to == Type::Real)
+}
+
+// A diagnostic that doesn't stop the program from running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub msg: String,
+    pub line: usize,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "warning: {} (line {})", self.msg, self.line)
+    }
+}
+
+// Variables that are assigned, declared, read into, or used as a loop
+// variable, but never appear in an expression.  Variables are matched by
+// name alone, ignoring scope; each is reported once, at the line where
+// it first gets a value, in order of those lines.
+pub fn unused_variables(stmts: &[Stmt]) -> Vec<Warning> {
+    let mut usage = Usage { defs: vec![], used: HashSet::new() };
+    usage.visit_program(stmts);
+    let used = usage.used;
+    usage.defs.into_iter()
+        .filter(|(name, _)| !used.contains(name))
+        .map(|(name, line)| Warning {
+            msg: format!("'{}' is assigned but never used", name), line })
+        .collect()
+}
+
+// Declarations of a variable with the same name as one in an enclosing
+// scope, which hide it for the rest of their block.  (Redeclaring a name
+// in the same scope is an error; see check().)
+pub fn shadowed_variables(stmts: &[Stmt]) -> Vec<Warning> {
+    let mut resolver = Resolver::new();
+    resolver.visit_program(stmts);
+    resolver.warnings
+}
+
+// Reads of a variable declared without a value before it's been
+// assigned one, scoped as in Checker.  An assignment counts if it comes
+// earlier in the text, even in a branch that might not run, so a read is
+// only reported when nothing could have set the variable first; a read
+// at the top of a loop that assigns further down is reported, since the
+// first time round it sees the 0.  Each variable is reported once.
+pub fn unassigned_reads(stmts: &[Stmt]) -> Vec<Warning> {
+    let mut assigned = Assigned { scopes: vec![HashMap::new()], warnings: vec![] };
+    assigned.visit_program(stmts);
+    assigned.warnings
+}
+
+struct Usage {
+    defs: Vec<(String, usize)>,     // first definition of each name
+    used: HashSet<String>,
+}
+
+impl Visitor for Usage {
+    fn visit_target(&mut self, name: &str, span: Span) {
+        if !self.defs.iter().any(|(n, _)| n == name) {
+            self.defs.push((name.to_string(), span.start_line));
+        }
+    }
+
+    fn visit_ident(&mut self, name: &str, _span: Span) {
+        self.used.insert(name.to_string());
+    }
+}
+
+// Whether each variable in scope has been given a value yet.
+struct Assigned {
+    scopes: Vec<HashMap<String, bool>>,     // innermost last
+    warnings: Vec<Warning>,
+}
+
+impl Assigned {
+    fn declare(&mut self, name: &str, assigned: bool) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), assigned);
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut bool> {
+        self.scopes.iter_mut().rev().filter_map(|s| s.get_mut(name)).next()
+    }
+}
+
+impl Visitor for Assigned {
+    fn visit_body(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        visit::walk_stmts(self, stmts);
+        self.scopes.pop();
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match *stmt {
+            Stmt::Decl { ref name, ref expr, .. } => {
+                if let Some(ref expr) = *expr {
+                    self.visit_expr(expr);
+                }
+                self.declare(name, expr.is_some());
+            }
+            Stmt::Const { ref name, ref expr, .. } => {
+                self.visit_expr(expr);
+                self.declare(name, true);
+            }
+            Stmt::Read { tp: Some(_), ref names, .. } => {
+                for name in names {
+                    self.declare(name, true);
+                }
+            }
+            Stmt::For { ref var, ref from, ref to, ref body, .. } => {
+                self.visit_expr(from);
+                self.visit_expr(to);
+                self.scopes.push(HashMap::new());
+                self.declare(var, true);
+                self.visit_body(body);
+                self.scopes.pop();
+            }
+            _ => visit::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_target(&mut self, name: &str, _span: Span) {
+        if let Some(assigned) = self.lookup(name) {
+            *assigned = true;
+        }
+    }
+
+    fn visit_ident(&mut self, name: &str, span: Span) {
+        if let Some(assigned) = self.lookup(name) {
+            if !*assigned {
+                // once is enough
+                *assigned = true;
+                self.warnings.push(Warning {
+                    msg: format!("'{}' read before assignment", name), line: span.start_line });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse_src;
+
+    fn errors(src: &str) -> Vec<String> {
+        check(&parse_src(src)).iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn declared_variables_are_fine() {
+        assert!(errors("read int n\nread int x\nx := n * 2").is_empty());
+    }
+
+    #[test]
+    fn undeclared_variables() {
+        assert_eq!(errors("y := x + 1"), vec![
+            "use of undeclared variable 'x' on line 1",
+            "use of undeclared variable 'y' on line 1",
+        ]);
+    }
+
+    #[test]
+    fn redeclaration_in_same_scope() {
+        assert_eq!(errors("read int n\nread real n"),
+            vec!["redeclaration of variable 'n' on line 2"]);
+        assert_eq!(errors("read int m, n, m"),
+            vec!["redeclaration of variable 'm' on line 1"]);
+    }
+
+    #[test]
+    fn type_mismatches() {
+        assert_eq!(errors("read int i\nread real r\ni := r"),
+            vec!["type mismatch: cannot assign real to int 'i' on line 3"]);
+        assert!(errors("read real r\nr := -r / 2.0 + 1.5").is_empty());
+        assert_eq!(errors("read int i\nread real r\ndo check i < r od"),
+            vec!["type mismatch: cannot compare int and real on line 3"]);
+        assert_eq!(errors("read int i\nread real r\nwrite (i < 2 ? i : r)"),
+            vec!["type mismatch: conditional branches are int and real on line 3"]);
+        assert_eq!(errors("read int i\nread real r\nwrite (i < r ? i : 2)"),
+            vec!["type mismatch: cannot compare int and real on line 3"]);
+        assert_eq!(errors("read int i\nread real r\ni := (i < 2 ? r : 1.5)"),
+            vec!["type mismatch: cannot assign real to int 'i' on line 3"]);
+    }
+
+    #[test]
+    fn ints_are_promoted_to_real() {
+        // int + real
+        assert!(errors("read int i\nread real r\nwrite i * 2 + r").is_empty());
+        assert!(errors("read int i\nreal r := 2 ** i % 1.5").is_empty());
+        // real := int + 1
+        assert!(errors("read int i\nread real r\nr := i + 1").is_empty());
+        assert!(errors("real r := 1\nconst real half := 1 / 2").is_empty());
+        // int := int + real
+        assert_eq!(errors("read int i\nread real r\ni := i + r"),
+            vec!["type mismatch: cannot assign real to int 'i' on line 3"]);
+        assert_eq!(errors("int i := 1\ni := i * (2 - 0.5) + 1"),
+            vec!["type mismatch: cannot assign real to int 'i' on line 2"]);
+        // conversions are still checked
+        assert_eq!(errors("read int i\nwrite trunc(i + 1)"),
+            vec!["type mismatch: trunc expects real, found int on line 2"]);
+        assert!(errors("read int i\nwrite trunc(i + 1.0)").is_empty());
+    }
+
+    #[test]
+    fn bitwise_operators_take_int() {
+        assert!(errors("read int i\nwrite i & 3 | i ^ 1").is_empty());
+        assert_eq!(errors("write 6.0 & 3"),
+            vec!["type mismatch: bitwise operators take int, found real on line 1"]);
+        assert_eq!(errors("read real r\nint i := 1 | trunc(r) ^ float(2)"),
+            vec!["type mismatch: bitwise operators take int, found real on line 2"]);
+    }
+
+    #[test]
+    fn conversions() {
+        assert!(errors("read int i\nread real r\ni := trunc(r) + i").is_empty());
+        assert!(errors("read int i\nread real r\nr := float(i * 2) / r").is_empty());
+        assert_eq!(errors("read int i\nwrite trunc(i)"),
+            vec!["type mismatch: trunc expects real, found int on line 2"]);
+        assert_eq!(errors("read real r\nwrite float(r)"),
+            vec!["type mismatch: float expects int, found real on line 2"]);
+    }
+
+    #[test]
+    fn for_loop_variable() {
+        // i is only in scope in the body
+        assert_eq!(errors("for i := 1 to 2.5 do write i od write i"), vec![
+            "use of undeclared variable 'i' on line 1",
+            "type mismatch: for loop bounds must be int on line 1",
+        ]);
+    }
+
+    #[test]
+    fn nested_scopes() {
+        assert_eq!(errors("read int n\ndo real n := 1.5 od\nread m"),
+            vec!["use of undeclared variable 'm' on line 3"]);
+    }
+
+    fn warnings(src: &str) -> Vec<String> {
+        unused_variables(&parse_src(src)).iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn unused_variables_are_reported_once() {
+        assert!(warnings("read int n\nint sq := n * n\nwrite sq").is_empty());
+        assert_eq!(warnings("read int n, tmp\nint tmp2 := n\ntmp2 := 3\nwrite n"), vec![
+            "warning: 'tmp' is assigned but never used (line 1)",
+            "warning: 'tmp2' is assigned but never used (line 2)",
+        ]);
+        // used in a condition or a loop bound, or only by itself
+        assert!(warnings("read int n\nint i := 0\ndo check i < n i := i + 1 od").is_empty());
+        assert!(warnings("read int n\nfor i := 1 to n do write i od").is_empty());
+        assert_eq!(warnings("int x := 1\nx := x + 1\nfor i := 1 to 3 do write 0 od"),
+            vec!["warning: 'i' is assigned but never used (line 3)"]);
+    }
+
+    fn shadows(src: &str) -> Vec<String> {
+        shadowed_variables(&parse_src(src)).iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn shadowing_is_a_warning() {
+        let src = "int x := 1\nif x > 0\n  real x := 2.0\n  write x * 2.0\nfi\nwrite x + 1";
+        assert_eq!(shadows(src),
+            vec!["warning: declaration of 'x' shadows an earlier one (line 3)"]);
+        // inner uses resolve to the inner declaration, outer ones to the outer
+        assert!(errors(src).is_empty());
+        // the same name in sibling blocks shadows nothing
+        assert!(shadows("do int t := 1 write t od\ndo real t := 2.0 write t od").is_empty());
+        assert_eq!(shadows("read int i\ndo for i := 1 to 3 do read real n od od\nread n"),
+            vec!["warning: declaration of 'i' shadows an earlier one (line 2)"]);
+    }
+
+    fn unassigned(src: &str) -> Vec<String> {
+        unassigned_reads(&parse_src(src)).iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn read_before_assignment() {
+        assert!(errors("int x\nreal r\nwrite x, r").is_empty());
+        assert_eq!(unassigned("int x\nwrite x + 1\nwrite x"),
+            vec!["warning: 'x' read before assignment (line 2)"]);
+        assert_eq!(unassigned("real r\nr := r * 2.0"),
+            vec!["warning: 'r' read before assignment (line 2)"]);
+        // an earlier assignment, read, or initializer counts, even in a branch
+        assert!(unassigned("int x\nx := 1\nwrite x").is_empty());
+        assert!(unassigned("int x\nread x\nwrite x\nint y := x\nwrite y").is_empty());
+        assert!(unassigned("int x\nif 1 < 2 x := 1 fi\nwrite x").is_empty());
+        // but not a later one, even in a loop
+        assert_eq!(unassigned("int x\ndo check x < 3\nx := x + 1 od"),
+            vec!["warning: 'x' read before assignment (line 2)"]);
+        // an inner declaration is a different variable
+        assert_eq!(unassigned("int x := 1\ndo int x\nwrite x od\nwrite x"),
+            vec!["warning: 'x' read before assignment (line 3)"]);
+    }
+
+    #[test]
+    fn check_needs_a_loop() {
+        assert!(errors("int i := 0\ndo check i < 3 i := i + 1 od").is_empty());
+        assert!(errors("for i := 1 to 3 do if i > 1 check i < 3 fi od").is_empty());
+        assert!(errors("do do check 1 < 2 od check 2 < 3 od").is_empty());
+        assert_eq!(errors("int i := 0\ncheck i < 3"),
+            vec!["check outside of a do or for loop on line 2"]);
+        assert_eq!(errors("do write 1 od\nif 1 < 2\ncheck 1 < 2\nfi"),
+            vec!["check outside of a do or for loop on line 3"]);
+    }
+
+    #[test]
+    fn chained_assignments() {
+        assert!(errors("int a := 0\nint b := 0\na := b := 3").is_empty());
+        assert!(errors("real a := 0.0\nint b := 0\na := b := 3").is_empty());
+        // the value is b's, promoted to real
+        assert_eq!(errors("int a := 0\nreal b := 0.0\na := b := 3"), vec![
+            "type mismatch: cannot assign real to int 'a' on line 3",
+        ]);
+        assert_eq!(errors("int a := 0\nreal b := 0.0\na := b := 3.0"), vec![
+            "type mismatch: cannot assign real to int 'a' on line 3",
+        ]);
+        assert_eq!(errors("const int k := 1\nk := c := 2"), vec![
+            "use of undeclared variable 'c' on line 2",
+            "cannot assign to constant 'k' on line 2",
+        ]);
+    }
+
+    #[test]
+    fn constants_are_read_only() {
+        assert!(errors("const int pi_approx := 3\nint r := 2\nwrite pi_approx * r * r").is_empty());
+        assert_eq!(errors("const int pi_approx := 3\npi_approx := 4"),
+            vec!["cannot assign to constant 'pi_approx' on line 2"]);
+        assert_eq!(errors("const real e := 2.7\nread e\nread real e"), vec![
+            "cannot assign to constant 'e' on line 2",
+            "redeclaration of variable 'e' on line 3",
+        ]);
+        assert_eq!(errors("const int c := 1.5"),
+            vec!["type mismatch: cannot assign real to int 'c' on line 1"]);
+        // an inner declaration hides the constant
+        assert!(errors("const int c := 1\ndo int c := 2\nc := 3 od").is_empty());
+    }
+}
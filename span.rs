@@ -0,0 +1,58 @@
+///////////////////////////////////////////////////////////////////////////////
+//  Source ranges
+//
+//  Every token and syntax tree node records the stretch of source it
+//  came from, so a diagnostic can point at exactly that.  Lines are
+//  1-based and columns 0-based display columns, as in input.rs; the end
+//  is exclusive, so a one-character token at line 2, col 5 spans
+//  2:5-2:6.  A node's span is the union of its children's.
+//
+
+use std::cmp;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize))]
+pub struct Span {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+impl Span {
+    pub fn new(start_line: usize, start_col: usize, end_line: usize, end_col: usize) -> Span {
+        Span { start_line, start_col, end_line, end_col }
+    }
+
+    // The smallest span covering both a and b (and anything between).
+    pub fn merge(a: Span, b: Span) -> Span {
+        let start = cmp::min((a.start_line, a.start_col), (b.start_line, b.start_col));
+        let end = cmp::max((a.end_line, a.end_col), (b.end_line, b.end_col));
+        Span::new(start.0, start.1, end.0, end.1)
+    }
+}
+
+// line:col-line:col
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}-{}:{}", self.start_line, self.start_col, self.end_line, self.end_col)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_covers_both() {
+        let a = Span::new(1, 4, 1, 6);
+        let b = Span::new(2, 0, 3, 2);
+        assert_eq!(Span::merge(a, b), Span::new(1, 4, 3, 2));
+        assert_eq!(Span::merge(b, a), Span::new(1, 4, 3, 2));
+        // on one line, by column; and a span inside another adds nothing
+        assert_eq!(Span::merge(Span::new(1, 7, 1, 9), a), Span::new(1, 4, 1, 9));
+        assert_eq!(Span::merge(Span::new(2, 1, 2, 2), b), b);
+        assert_eq!(b.to_string(), "2:0-3:2");
+    }
+}
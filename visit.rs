@@ -0,0 +1,163 @@
+///////////////////////////////////////////////////////////////////////////////
+//  AST traversal
+//
+//  A pass over the syntax tree implements Visitor, overriding just the
+//  hooks it cares about.  Each hook's default calls the matching walk_*
+//  function, which visits the node's children in source order, so an
+//  override can do its work before, after, or instead of the walk.
+//
+//  Names given a value -- by an assignment, a declaration, a read, or as
+//  a for-loop variable -- are reported to visit_target(), after any
+//  expression that supplies the value.  Uses of a variable in an
+//  expression go to visit_ident().
+//
+
+use crate::ast::*;
+
+pub trait Visitor: Sized {
+    // The statements of a program.  Not called for nested bodies.
+    fn visit_program(&mut self, stmts: &[Stmt]) {
+        walk_stmts(self, stmts);
+    }
+
+    // The body of an if/elsif/else branch, a do loop, or a for loop.
+    fn visit_body(&mut self, stmts: &[Stmt]) {
+        walk_stmts(self, stmts);
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt);
+    }
+
+    fn visit_target(&mut self, _name: &str, _span: Span) {}
+
+    fn visit_cond(&mut self, cond: &Cond) {
+        walk_cond(self, cond);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_ident(&mut self, _name: &str, _span: Span) {}
+
+    fn visit_binary(&mut self, _op: BinOp, lhs: &Expr, rhs: &Expr, _op_span: Span) {
+        self.visit_expr(lhs);
+        self.visit_expr(rhs);
+    }
+}
+
+pub fn walk_stmts<V: Visitor>(v: &mut V, stmts: &[Stmt]) {
+    for stmt in stmts {
+        v.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(v: &mut V, stmt: &Stmt) {
+    match *stmt {
+        Stmt::Assign { ref name, ref expr, span }
+        | Stmt::Const { ref name, ref expr, span, .. } => {
+            v.visit_expr(expr);
+            v.visit_target(name, span);
+        }
+        // declared without a value, the variable is still a target
+        Stmt::Decl { ref name, ref expr, span, .. } => {
+            if let Some(ref expr) = *expr {
+                v.visit_expr(expr);
+            }
+            v.visit_target(name, span);
+        }
+        Stmt::Read { ref names, span, .. } => {
+            for name in names {
+                v.visit_target(name, span);
+            }
+        }
+        Stmt::Write { ref args, .. } => {
+            for arg in args {
+                if let WriteArg::Expr(ref expr) = *arg {
+                    v.visit_expr(expr);
+                }
+            }
+        }
+        Stmt::If { ref branches, ref else_body, .. } => {
+            for (cond, body) in branches {
+                v.visit_cond(cond);
+                v.visit_body(body);
+            }
+            if let Some(ref else_body) = *else_body {
+                v.visit_body(else_body);
+            }
+        }
+        Stmt::Do { ref body, .. } => v.visit_body(body),
+        Stmt::For { ref var, ref from, ref to, ref body, span } => {
+            v.visit_expr(from);
+            v.visit_expr(to);
+            v.visit_target(var, span);
+            v.visit_body(body);
+        }
+        Stmt::Check { ref cond, .. } | Stmt::Assert { ref cond, .. } => v.visit_cond(cond),
+    }
+}
+
+pub fn walk_cond<V: Visitor>(v: &mut V, cond: &Cond) {
+    v.visit_expr(&cond.lhs);
+    v.visit_expr(&cond.rhs);
+}
+
+pub fn walk_expr<V: Visitor>(v: &mut V, expr: &Expr) {
+    match *expr {
+        Expr::Ident { ref name, span } => v.visit_ident(name, span),
+        Expr::ILit(..) | Expr::RLit(..) => {}
+        Expr::Neg { ref operand, .. } | Expr::Trunc(ref operand, _)
+        | Expr::Float(ref operand, _) => v.visit_expr(operand),
+        Expr::Binary { op, ref lhs, ref rhs, op_span, .. } => v.visit_binary(op, lhs, rhs, op_span),
+        Expr::Assign { ref name, ref value, span } => {
+            v.visit_expr(value);
+            v.visit_target(name, span);
+        }
+        Expr::Ternary { ref cond, ref then, ref otherwise, .. } => {
+            v.visit_cond(cond);
+            v.visit_expr(then);
+            v.visit_expr(otherwise);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::parse_src;
+
+    // Records what it's shown, to check the order of the walk.
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    impl Visitor for Trace {
+        fn visit_body(&mut self, stmts: &[Stmt]) {
+            self.0.push("{".to_string());
+            walk_stmts(self, stmts);
+            self.0.push("}".to_string());
+        }
+        fn visit_target(&mut self, name: &str, _span: Span) {
+            self.0.push(format!("{}:=", name));
+        }
+        fn visit_ident(&mut self, name: &str, _span: Span) {
+            self.0.push(name.to_string());
+        }
+        fn visit_binary(&mut self, op: BinOp, lhs: &Expr, rhs: &Expr, _op_span: Span) {
+            self.visit_expr(lhs);
+            self.visit_expr(rhs);
+            self.0.push(format!("{:?}", op));
+        }
+    }
+
+    #[test]
+    fn walks_in_source_order() {
+        let src = "read n\nx := z := n * (y - 1)\nfor i := a to b do if i < c write -i else d := 0 fi od";
+        let stmts = parse_src(src);
+        let mut trace = Trace::default();
+        trace.visit_program(&stmts);
+        assert_eq!(trace.0.join(" "),
+            "n:= n y Sub Mul z:= x:= a b i:= { i c { i } { d:= } }");
+    }
+}